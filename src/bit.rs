@@ -1,61 +1,66 @@
-use crate::bit::Bit::{S0, S1, W0, W1, Z};
+use crate::bit::Bit::{S0, S1, W0, W1, X, Z};
 
-fn print_grid() {
-    let list = [Bit::S0, Bit::S1, Bit::W0, Bit::W1, Bit::Z];
-
-    print!("\t");
-    for &b in &list {
-        print!("{:>5}", format!("{:?}", b));
-    }
-    println!();
-    println!();
-
-    for &a in &list {
-        print!("{:?}\t", a);
-        for &b in &list {
-            let str = Bit::pmos(a, b)
-                .map(|s| format!("{:?}", s))
-                .unwrap_or("X".to_string());
-            print!("{:>5}", str);
-        }
-        println!()
-    }
-}
-
-#[derive(Copy, Clone, Debug)]
-enum Bit {
+/// The value of a single row of a `Signal`. The tables here are the reference for the bit-parallel operations on
+/// `Signal`, which have to agree with them row by row.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Bit {
     S0,
     S1,
     W0,
     W1,
     Z,
+    /// contention or an unknown level
+    X,
 }
 
-static CONNECT_TABLE: [[Option<Bit>; 5]; 5] = [
-    [Some(S0), None, Some(S0), None, Some(S0)],
-    [None, Some(S1), None, Some(S1), Some(S1)],
-    [Some(S0), None, Some(W0), None, Some(W0)],
-    [None, Some(S1), None, Some(W1), Some(W1)],
-    [Some(S0), Some(S1), Some(W0), Some(W1), Some(Z)],
+static CONNECT_TABLE: [[Option<Bit>; 6]; 6] = [
+    [Some(S0), None, Some(S0), None, Some(S0), None],
+    [None, Some(S1), None, Some(S1), Some(S1), None],
+    [Some(S0), None, Some(W0), None, Some(W0), None],
+    [None, Some(S1), None, Some(W1), Some(W1), None],
+    [Some(S0), Some(S1), Some(W0), Some(W1), Some(Z), Some(X)],
+    [None, None, None, None, Some(X), None],
+];
+
+static RESOLVE_TABLE: [[Bit; 6]; 6] = [
+    [S0, X, S0, S0, S0, X],
+    [X, S1, S1, S1, S1, X],
+    [S0, S1, W0, X, W0, X],
+    [S0, S1, X, W1, W1, X],
+    [S0, S1, W0, W1, Z, X],
+    [X, X, X, X, X, X],
+];
+
+static PMOS_TABLE: [[Option<Bit>; 6]; 6] = [
+    [Some(W0), Some(S1), Some(W0), Some(W1), Some(Z), Some(X)],
+    [Some(Z), Some(Z), Some(Z), Some(Z), Some(Z), Some(Z)],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
 ];
 
-static PMOS_TABLE: [[Option<Bit>; 5]; 5] = [
-    [Some(W0), Some(S1), Some(W0), Some(W1), Some(Z)],
-    [Some(Z), Some(Z), Some(Z), Some(Z), Some(Z)],
-    [None, None, None, None, None],
-    [None, None, None, None, None],
-    [None, None, None, None, None],
+static NMOS_TABLE: [[Option<Bit>; 6]; 6] = [
+    [Some(Z), Some(Z), Some(Z), Some(Z), Some(Z), Some(Z)],
+    [Some(S0), Some(W1), Some(W0), Some(W1), Some(Z), Some(X)],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
 ];
 
-static NMOS_TABLE: [[Option<Bit>; 5]; 5] = [
-    [Some(Z), Some(Z), Some(Z), Some(Z), Some(Z)],
-    [Some(S0), Some(W1), Some(W0), Some(W1), Some(Z)],
-    [None, None, None, None, None],
-    [None, None, None, None, None],
-    [None, None, None, None, None],
+static TRANSMISSION_GATE_TABLE: [[Option<Bit>; 6]; 6] = [
+    [Some(Z), Some(Z), Some(Z), Some(Z), Some(Z), Some(Z)],
+    [Some(S0), Some(S1), Some(W0), Some(W1), Some(Z), Some(X)],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
+    [None, None, None, None, None, None],
 ];
 
 impl Bit {
+    pub const ALL: [Bit; 6] = [S0, S1, W0, W1, Z, X];
+
     fn num(self: Bit) -> usize {
         match self {
             S0 => 0,
@@ -63,18 +68,90 @@ impl Bit {
             W0 => 2,
             W1 => 3,
             Z => 4,
+            X => 5,
+        }
+    }
+
+    /// The `(low, high, strong)` bits of this value in a `Signal`.
+    pub fn levels(self) -> (bool, bool, bool) {
+        match self {
+            S0 => (true, false, true),
+            S1 => (false, true, true),
+            W0 => (true, false, false),
+            W1 => (false, true, false),
+            Z => (false, false, false),
+            X => (true, true, true),
         }
     }
 
-    fn connect(a: Bit, b: Bit) -> Option<Bit> {
+    /// The inverse of `levels`, `None` for the combinations that never appear in a `Signal`.
+    pub fn from_levels(levels: (bool, bool, bool)) -> Option<Bit> {
+        Bit::ALL.iter().copied().find(|b| b.levels() == levels)
+    }
+
+    pub fn connect(a: Bit, b: Bit) -> Option<Bit> {
         CONNECT_TABLE[a.num()][b.num()]
     }
 
-    fn pmos(g: Bit, d: Bit) -> Option<Bit> {
+    pub fn resolve(a: Bit, b: Bit) -> Bit {
+        RESOLVE_TABLE[a.num()][b.num()]
+    }
+
+    pub fn connect_ratioed(a: Bit, b: Bit) -> Option<Bit> {
+        Some(Bit::resolve(a, b)).filter(|&r| r != X)
+    }
+
+    pub fn pmos(g: Bit, d: Bit) -> Option<Bit> {
         PMOS_TABLE[g.num()][d.num()]
     }
 
-    fn nmos(g: Bit, d: Bit) -> Option<Bit> {
+    pub fn nmos(g: Bit, d: Bit) -> Option<Bit> {
         NMOS_TABLE[g.num()][d.num()]
     }
-}
\ No newline at end of file
+
+    pub fn transmission_gate(g: Bit, d: Bit) -> Option<Bit> {
+        TRANSMISSION_GATE_TABLE[g.num()][d.num()]
+    }
+
+    pub fn pmos_weak_gate(g: Bit, d: Bit) -> Bit {
+        Bit::weak_gate(Bit::pmos, g, d)
+    }
+
+    pub fn nmos_weak_gate(g: Bit, d: Bit) -> Bit {
+        Bit::weak_gate(Bit::nmos, g, d)
+    }
+
+    pub fn transmission_gate_weak_gate(g: Bit, d: Bit) -> Bit {
+        Bit::weak_gate(Bit::transmission_gate, g, d)
+    }
+
+    /// A weak gate switches like the strong one with the same level but weakens the output, a floating gate
+    /// makes a driven `d` unknown.
+    fn weak_gate(f: fn(Bit, Bit) -> Option<Bit>, g: Bit, d: Bit) -> Bit {
+        match g {
+            S0 | S1 => f(g, d).unwrap(),
+            W0 => Bit::resistor(f(S0, d).unwrap()),
+            W1 => Bit::resistor(f(S1, d).unwrap()),
+            Z | X => if d == Z { Z } else { X },
+        }
+    }
+
+    /// A weak copy of `d`, `X` stays `X`.
+    pub fn resistor(d: Bit) -> Bit {
+        match d {
+            S0 => W0,
+            S1 => W1,
+            _ => d,
+        }
+    }
+
+    pub fn complement(self) -> Bit {
+        match self {
+            S0 => S1,
+            S1 => S0,
+            W0 => W1,
+            W1 => W0,
+            _ => self,
+        }
+    }
+}
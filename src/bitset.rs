@@ -0,0 +1,145 @@
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+use crate::signal::BitSet;
+
+/// A bitset with `64 * N` rows, for truth tables that don't fit in a primitive integer.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct ArrayBitSet<const N: usize>(pub [u64; N]);
+
+impl<const N: usize> ArrayBitSet<N> {
+    fn combine(self, other: Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        let mut words = self.0;
+        for (word, &other) in words.iter_mut().zip(&other.0) {
+            *word = f(*word, other);
+        }
+        ArrayBitSet(words)
+    }
+}
+
+impl<const N: usize> BitSet for ArrayBitSet<N> {
+    fn size() -> usize {
+        64 * N
+    }
+
+    fn zero() -> Self {
+        ArrayBitSet([0; N])
+    }
+
+    fn get(&self, index: usize) -> bool {
+        (self.0[index / 64] >> (index % 64)) & 1 != 0
+    }
+
+    fn set(&mut self, index: usize, value: bool) {
+        let mask = 1 << (index % 64);
+        if value {
+            self.0[index / 64] |= mask;
+        } else {
+            self.0[index / 64] &= !mask;
+        }
+    }
+}
+
+/// A bitset with any number of rows, stored on the heap. All rows past the stored words have the value `rest`,
+/// so a set and its complement take up the same space.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct DynBitSet {
+    /// never ends with a word that is equal to the fill, so equal sets are always stored the same way
+    words: Vec<u64>,
+    rest: bool,
+}
+
+impl DynBitSet {
+    fn fill(&self) -> u64 {
+        if self.rest { !0 } else { 0 }
+    }
+
+    fn word(&self, index: usize) -> u64 {
+        self.words.get(index).copied().unwrap_or_else(|| self.fill())
+    }
+
+    fn normalize(mut self) -> Self {
+        while self.words.last() == Some(&self.fill()) {
+            self.words.pop();
+        }
+        self
+    }
+
+    fn combine(self, other: Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        let len = self.words.len().max(other.words.len());
+        DynBitSet {
+            words: (0..len).map(|i| f(self.word(i), other.word(i))).collect(),
+            rest: f(self.fill(), other.fill()) != 0,
+        }.normalize()
+    }
+}
+
+impl BitSet for DynBitSet {
+    fn size() -> usize {
+        usize::MAX
+    }
+
+    fn zero() -> Self {
+        DynBitSet { words: vec![], rest: false }
+    }
+
+    fn get(&self, index: usize) -> bool {
+        (self.word(index / 64) >> (index % 64)) & 1 != 0
+    }
+
+    fn set(&mut self, index: usize, value: bool) {
+        if self.get(index) == value { return; }
+
+        let fill = self.fill();
+        if self.words.len() <= index / 64 {
+            self.words.resize(index / 64 + 1, fill);
+        }
+        self.words[index / 64] ^= 1 << (index % 64);
+
+        *self = std::mem::replace(self, DynBitSet::zero()).normalize();
+    }
+
+    fn ones(count: usize) -> Self {
+        let mut words = vec![!0; count / 64];
+        let partial = count % 64;
+        if partial > 0 {
+            words.push(!(!0 << partial));
+        }
+        DynBitSet { words, rest: false }
+    }
+}
+
+macro_rules! impl_bit_ops {
+    ($ty:ty, $($generics:tt)*) => {
+        impl<$($generics)*> BitAnd for $ty {
+            type Output = Self;
+            fn bitand(self, rhs: Self) -> Self { self.combine(rhs, |a, b| a & b) }
+        }
+
+        impl<$($generics)*> BitOr for $ty {
+            type Output = Self;
+            fn bitor(self, rhs: Self) -> Self { self.combine(rhs, |a, b| a | b) }
+        }
+
+        impl<$($generics)*> BitXor for $ty {
+            type Output = Self;
+            fn bitxor(self, rhs: Self) -> Self { self.combine(rhs, |a, b| a ^ b) }
+        }
+    };
+}
+
+impl_bit_ops!(ArrayBitSet<N>, const N: usize);
+impl_bit_ops!(DynBitSet,);
+
+impl<const N: usize> Not for ArrayBitSet<N> {
+    type Output = Self;
+    fn not(self) -> Self {
+        ArrayBitSet(self.0.map(|w| !w))
+    }
+}
+
+impl Not for DynBitSet {
+    type Output = Self;
+    fn not(self) -> Self {
+        DynBitSet { words: self.words.into_iter().map(|w| !w).collect(), rest: !self.rest }
+    }
+}
@@ -1,173 +1,201 @@
-use smallset::SmallSet;
-
-use crate::signal::{CareSignal, Query, Signal, BitSet};
-
-/*
-TODO:
-
-design datastructure, possibly using sets? or something more bitset-like?
-write functions to take and put in signals, make everything reversible
-
-actually write the algorithm
-
-try to alternate adding pmos and nmos, solutions probably have a ratio around 50/50
-
-*/
-
-fn find_solution<B: BitSet>(query: &Query<B>, max_gates: usize) {
-    let mut state = State {
-        power_signals: SmallSet::new(),
-        gate_signals: SmallSet::new(),
-        free_signals: SmallSet::new(),
-        outputs: query.outputs,
-        max_gates,
-    };
-
-    for &s in query.power {
-        state.power_signals.insert(s);
-    }
-    for &s in query.inputs {
-        state.gate_signals.insert(s);
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::pathfind::{SolveOptions, Solutions, SolveStats};
+use crate::signal::{BitSet, Query, Signal};
+use crate::solution::{Kind, Step};
+use crate::vec_set::VecSet;
+
+/// Iterative-deepening depth-first search, uses memory linear in the number of devices instead of
+/// keeping every visited position around like the bfs does.
+pub fn find_solution<'q, B: BitSet>(
+    query: &Query<'q, B>, options: &SolveOptions, solutions: &mut Solutions<'_, 'q, B>, stats: &mut SolveStats,
+) {
+    for max_gates in 0..=options.max_devices {
+        let mut state = State {
+            query,
+            built_signals: VecSet::default(),
+            free_signals: VecSet::default(),
+            series: BTreeMap::new(),
+            steps: Vec::new(),
+            size: 0,
+            max_gates,
+            max_series: options.max_series,
+            transmission_gates: options.transmission_gates,
+            gateless: options.gateless_kinds(),
+            weak_gates: options.weak_gates,
+            connect: options.connect(),
+            solutions: &mut *solutions,
+            stats: &mut *stats,
+        };
+
+        state.recurse();
+        if !solutions.is_empty() {
+            return;
+        }
     }
-
-    //TODO decide whether to do this or from_iter instead
-//    state.power_signals.extend(query.power.iter().copied());
-//    state.gate_signals.extend(query.inputs.iter().copied());
-    //TODO decide whether to add power to gate or not
-//    state.gate_signals.extend(query.power.iter().copied());
-
-
-    return state.recurse(0);
 }
 
-#[derive(Debug)]
-struct State<'a, B: BitSet> {
-    power_signals: SmallSet<[Signal<B>; 10]>,
-    gate_signals: SmallSet<[Signal<B>; 10]>,
-    free_signals: SmallSet<[Signal<B>; 10]>,
+/// The current position of the search, modified in place and restored when backtracking.
+/// The candidates are the same as in the bfs: power signals and inputs from the query together with all built
+/// signals, and new outputs can be merged with free signals that have not been used yet.
+struct State<'a, 's, 'q, B: BitSet> {
+    query: &'a Query<'q, B>,
 
-    outputs: &'a [CareSignal<B>],
+    built_signals: VecSet<Signal<B>>,
+    free_signals: VecSet<Signal<B>>,
+    /// the number of devices in series for each built signal, only tracked if there is a `max_series`
+    series: BTreeMap<Signal<B>, usize>,
+
+    steps: Vec<Step<B>>,
+    /// the size of the devices in `steps`, see `Kind::size`
+    size: usize,
     max_gates: usize,
+    max_series: Option<usize>,
+    transmission_gates: bool,
+    gateless: Vec<Kind>,
+    weak_gates: bool,
+    connect: fn(&Signal<B>, &Signal<B>) -> Option<Signal<B>>,
+
+    solutions: &'a mut Solutions<'s, 'q, B>,
+    stats: &'a mut SolveStats,
 }
 
-impl<'a, B: BitSet> State<'a, B> {
-    fn recurse(&mut self, i: usize) {
-        self.check_solution();
-        if i == self.max_gates { return; }
+impl<'a, 's, 'q, B: BitSet> State<'a, 's, 'q, B> {
+    /// Returns true if enough solutions were found.
+    fn recurse(&mut self) -> bool {
+        let i = self.steps.len();
 
-        let power_signals: Vec<Signal<B>> = self.power_signals.iter().copied().collect();
-        let gate_signals: Vec<Signal<B>> = self.gate_signals.iter().copied().collect();
-        let free_signals: Vec<Signal<B>> = self.free_signals.iter().copied().collect();
+        //earlier iterations didn't find a solution, so any solution found here has the minimum size
+        if self.check_solution() { return self.solutions.add(&self.steps); }
+        if self.size == self.max_gates { return false; }
 
-        for power in power_signals.iter().copied() {
-//            println!("{:-<2$}Picking power {:?}", "", power, i);
+        SolveStats::count(&mut self.stats.expanded_per_depth, i, 1);
 
-            let power_was_free = self.free_signals.remove(&power);
+        let power_signals: Vec<Signal<B>> = self.query.power.iter().chain(self.built_signals.iter()).cloned().collect();
+        let gate_signals: Vec<Signal<B>> = self.query.inputs.iter().chain(self.built_signals.iter()).cloned().collect();
 
-            for gate in gate_signals.iter().copied() {
-//                println!("{:-<2$}Picking gate {:?}", "", gate, i);
+        //alternate order of attempting pmos/nmos
+        let kinds = if i.is_multiple_of(2) { [Kind::PMOS, Kind::NMOS] } else { [Kind::NMOS, Kind::PMOS] };
 
-                /*let vcc = Signal::new_strong(0b1111_1111, 0b1111_1111);
-                let gnd = Signal::new_strong(0b0000_0000, 0b1111_1111);
-                let a = Signal::new_strong(0b0000_1111, 0b1111_1111);
-                let b = Signal::new_strong(0b0011_0011, 0b1111_1111);
-                let c = Signal::new_strong(0b0101_0101, 0b1111_1111);
+        for power in &power_signals {
+            let power_was_free = self.free_signals.remove(power);
 
-                if i == 0 && power == vcc && gate == a {
-                    println!("0")
-                }
-                if i == 1 && power == vcc && gate == b {
-                    println!("1")
-                }
-                if i == 2 && power == vcc && gate == c {
-                    println!("2")
-                }
-                if i == 3 && power == gnd && gate == a {
-                    println!("3")
-                }
-                if i == 4 && power == Signal::nmos(a, gnd, self.mask).unwrap() && gate == b {
-                    println!("4")
+            for gate in &gate_signals {
+                let gate_was_free = self.free_signals.remove(gate);
+
+                for &kind in &kinds {
+                    if self.add_device(kind, Some(gate), power) { return true; }
                 }
-                if i == 5 && power == Signal::nmos(b, Signal::nmos(a, gnd, self.mask).unwrap(), self.mask).unwrap() && gate == c {
-                    println!("5")
-                }*/
-
-                let gate_was_free = self.free_signals.remove(&gate);
-
-                //alternate order of attempting pmos/nmos
-                if i % 2 == 0 {
-                    self.add_device(Signal::pmos(gate, power), &free_signals, i);
-                    self.add_device(Signal::nmos(gate, power), &free_signals, i);
-                } else {
-                    self.add_device(Signal::nmos(gate, power), &free_signals, i);
-                    self.add_device(Signal::pmos(gate, power), &free_signals, i);
+
+                if self.transmission_gates {
+                    let inverted = gate.complement();
+                    if gate_signals.contains(&inverted) {
+                        let inverted_was_free = self.free_signals.remove(&inverted);
+                        if self.add_device(Kind::TransmissionGate, Some(gate), power) { return true; }
+                        if inverted_was_free {
+                            assert!(self.free_signals.insert(inverted));
+                        }
+                    }
                 }
 
                 if gate_was_free {
-                    assert!(self.free_signals.insert(gate));
+                    assert!(self.free_signals.insert(gate.clone()));
                 }
             }
 
-            if power_was_free {
-                assert!(self.free_signals.insert(power));
+            for kind in self.gateless.clone() {
+                if self.add_device(kind, None, power) { return true; }
             }
 
-//            debug_assert_eq!(self.power_signals, power_signals.iter().copied().collect());
-//            debug_assert_eq!(self.gate_signals, gate_signals.iter().copied().collect());
-//            debug_assert_eq!(self.free_signals, free_signals.iter().copied().collect());
-        }
-    }
-
-    fn check_solution(&self) {
-        for &CareSignal { signal, care } in self.outputs {
-            if care == self.mask {
-                //quick way out, just find signal that's entirely equal
-                if !self.power_signals.contains(&signal) { return; }
-            } else {
-                //need to check equality taking into account the mask
-                if !self.power_signals.iter()
-                    .any(|s| s.equals(signal, care)) { return; }
+            if power_was_free {
+                assert!(self.free_signals.insert(power.clone()));
             }
         }
 
-        //at this point we've found a solution
-        println!("Found solution, {:?}", self);
+        false
+    }
+
+    fn check_solution(&mut self) -> bool {
+        let start = Instant::now();
+        let built_signals = &self.built_signals;
+        let result = self.query.outputs.iter()
+            .all(|cs| built_signals.iter().any(|s| cs.matches(s)));
+        self.stats.done_time += start.elapsed();
+        result
     }
 
-    fn add_device(&mut self, result: Option<Signal<B>>, free_signals: &Vec<Signal<B>>, i: usize) {
-        if let Some(new) = result {
+    fn add_device(&mut self, kind: Kind, gate: Option<&Signal<B>>, power: &Signal<B>) -> bool {
+        if self.size + kind.size() > self.max_gates { return false; }
+
+        let series = match self.max_series {
+            None => 0,
+            Some(max_series) => {
+                let series = self.series.get(power).map_or(0, |&s| s) + 1;
+                if series > max_series { return false; }
+                series
+            }
+        };
+
+        if let Some(output) = kind.apply(gate, power, self.weak_gates) {
             //add as new free signal
-            self.add_as_free(new, i);
+            let step = Step { kind, power: power.clone(), gate: gate.cloned(), merge: None, output: output.clone() };
+            if self.add_as_free(step, series) { return true; }
 
-            //TODO is merging with a single one enough? try a 3-input NAND gate
             //merge with other free signal
-            for other in free_signals.iter().copied() {
-                //free signal could have been used to make this one, check again
-                if self.free_signals.remove(&other) {
-                    if let Some(combined) = Signal::connect(new, other) {
-                        self.add_as_free(combined, i);
+            let free_signals: Vec<Signal<B>> = self.free_signals.iter().cloned().collect();
+            for other in free_signals {
+                if let Some(combined) = (self.connect)(&output, &other) {
+                    assert!(self.free_signals.remove(&other));
+                    assert!(self.built_signals.remove(&other));
+                    let other_series = self.series.remove(&other);
+
+                    let step = Step {
+                        kind, power: power.clone(), gate: gate.cloned(), merge: Some(other.clone()), output: combined,
+                    };
+                    if self.add_as_free(step, series.max(other_series.unwrap_or(0))) {
+                        return true;
+                    }
+
+                    if let Some(other_series) = other_series {
+                        self.series.insert(other.clone(), other_series);
                     }
-                    assert!(self.free_signals.insert(other))
+                    assert!(self.built_signals.insert(other.clone()));
+                    assert!(self.free_signals.insert(other));
                 }
             }
         }
-    }
-
-    fn add_as_free(&mut self, new: Signal<B>, i: usize) {
-        if self.free_signals.insert(new) {
-            let power_is_new = self.power_signals.insert(new);
-            let gate_is_new = self.gate_signals.insert(new);
 
-            self.recurse(i + 1);
+        false
+    }
 
-            if power_is_new { assert!(self.power_signals.remove(&new)) }
-            if gate_is_new { assert!(self.gate_signals.remove(&new)) }
-            assert!(self.free_signals.remove(&new));
+    fn add_as_free(&mut self, step: Step<B>, series: usize) -> bool {
+        let output = step.output.clone();
+
+        if self.free_signals.insert(output.clone()) {
+            SolveStats::count(&mut self.stats.generated_per_depth, self.steps.len(), 1);
+            let built_is_new = self.built_signals.insert(output.clone());
+            let old_series = match self.max_series {
+                None => None,
+                Some(_) => self.series.insert(output.clone(), series),
+            };
+
+            self.size += step.kind.size();
+            self.steps.push(step);
+            if self.recurse() { return true; }
+            let step = self.steps.pop().unwrap();
+            self.size -= step.kind.size();
+
+            if self.max_series.is_some() {
+                match old_series {
+                    Some(old_series) => self.series.insert(output.clone(), old_series),
+                    None => self.series.remove(&output),
+                };
+            }
+            if built_is_new { assert!(self.built_signals.remove(&output)) }
+            assert!(self.free_signals.remove(&output));
         }
+
+        false
     }
 }
-
-pub fn main_custom<B: BitSet>(query: &Query<B>, max_gates: usize) {
-    let result = find_solution(&query, max_gates);
-    println!("{:?}", result);
-}
\ No newline at end of file
@@ -0,0 +1,226 @@
+use crate::bit::Bit;
+use crate::export::layout::Layout;
+use crate::export::{is_input, ports};
+use crate::signal::{BitSet, Query};
+use crate::solution::{Kind, NetId, NetOrigin, Solution};
+
+/// how far an output bus continues past its last device, to the open circle of the port
+const OUTPUT_LEAD: i32 = 20;
+/// gates on internal nets with more variables than this are labeled with the net name instead of an expression
+const MAX_VARIABLES: usize = 8;
+
+/// Write `solution` as a CircuiTikZ picture followed by the truth table of `query` as a `tabular`, for a document
+/// that uses the `circuitikz` package. The devices are in the same places as in `svg::to_svg`, with the layout in
+/// points. Gates on inputs are labeled with their name, gates on internal nets with an expression in the inputs
+/// that is derived from the rows of the net. The table has a column for each input, power net that is not a
+/// supply and output, and lists the rows in the same order as the signal strings.
+pub fn to_circuitikz<B: BitSet>(solution: &Solution<B>, query: &Query<B>, name: &str) -> String {
+    let layout = Layout::new(solution);
+    let mut lines = vec![
+        format!("% {}: {} devices, needs \\usepackage{{circuitikz}}", name, solution.device_count()),
+        "\\begin{tikzpicture}[x=1pt, y=-1pt, baseline=(current bounding box.center)]".to_string(),
+    ];
+
+    for (id, net) in solution.nets.iter().enumerate() {
+        let (y, (start, end)) = match layout.bus[id] {
+            Some(bus) => (layout.net_y[id], bus),
+            None => continue,
+        };
+        let label = format!("node[left] {{${}$}}", math_name(&net.name));
+        if solution.outputs.contains(&id) {
+            let end = end + OUTPUT_LEAD;
+            lines.push(format!("    \\draw ({},{}) {} -- ({},{}) node[ocirc] {{}};", start, y, label, end, y));
+        } else {
+            lines.push(format!("    \\draw ({},{}) {} -- ({},{});", start, y, label, end, y));
+        }
+    }
+
+    let mut count = 0;
+    for (i, device) in solution.devices.iter().enumerate() {
+        let (x, y) = layout.device_pos[i];
+        let (source_y, drain_y) = (layout.net_y[device.source], layout.net_y[device.drain]);
+        if device.kind == Kind::Resistor {
+            lines.push(format!("    \\draw ({},{}) to[R, *-*] ({},{});", x, source_y, x, drain_y));
+            continue;
+        }
+
+        //every transistor hangs from its source on the bus, flipped so it reaches towards the drain
+        let source_up = layout.terminal_y[i].0 < y;
+        for transistor in device.transistors() {
+            count += 1;
+            let pmos = transistor.kind == Kind::PMOS || transistor.kind == Kind::PullUp;
+            let mirrored = device.kind == Kind::TransmissionGate && pmos;
+            let mut options = vec![if pmos { "pmos" } else { "nmos" }, "anchor=S"];
+            if source_up != pmos {
+                options.push("yscale=-1");
+            }
+            if mirrored {
+                options.push("xscale=-1");
+            }
+
+            lines.push(format!(
+                "    \\draw ({},{}) node[circ] {{}} node[{}] (m{}) {{}};", x, source_y, options.join(", "), count,
+            ));
+            lines.push(format!("    \\draw (m{0}.D) -- (m{0}.D |- 0,{1}) node[circ] {{}};", count, drain_y));
+            let gate = match (device.kind, transistor.gate) {
+                (Kind::PullUp, _) => "node[ground] {}".to_string(),
+                (Kind::PullDown, _) => "node[vcc] {}".to_string(),
+                (_, Some(gate)) => {
+                    format!("node[{}] {{${}$}}", if mirrored { "right" } else { "left" }, gate_label(solution, gate))
+                }
+                (_, None) => panic!("transistor without gate"),
+            };
+            lines.push(format!("    \\draw (m{}.G) {};", count, gate));
+        }
+    }
+    lines.push("\\end{tikzpicture}".to_string());
+
+    lines.push("\\quad".to_string());
+    lines.extend(truth_table(solution, query));
+    lines.join("\n") + "\n"
+}
+
+fn truth_table<B: BitSet>(solution: &Solution<B>, query: &Query<B>) -> Vec<String> {
+    let inputs: Vec<NetId> = ports(solution).into_iter().filter(|&id| is_input(solution, id)).collect();
+    let mut header: Vec<String> = inputs.iter().map(|&id| math_name(solution.net_name(id))).collect();
+    header.extend(solution.outputs.iter().map(|&id| math_name(solution.net_name(id))));
+    let header: Vec<String> = header.iter().map(|name| format!("${}$", name)).collect();
+
+    let mut lines = vec![
+        format!("\\begin{{tabular}}{{{}|{}}}", "c".repeat(inputs.len()), "c".repeat(solution.outputs.len())),
+        format!("    {} \\\\", header.join(" & ")),
+        "    \\hline".to_string(),
+    ];
+    for row in (0..query.rows()).rev() {
+        let mut cells: Vec<&str> = inputs.iter().map(|&id| value(solution.nets[id].signal.get_row(row))).collect();
+        cells.extend(query.outputs.iter().map(|output| {
+            if output.any_strong.get(row) {
+                "?"
+            } else if !output.care.get(row) {
+                "-"
+            } else {
+                value(output.signal.get_row(row))
+            }
+        }));
+        lines.push(format!("    {} \\\\", cells.join(" & ")));
+    }
+    lines.push("\\end{tabular}".to_string());
+    lines
+}
+
+/// The label of a gate, in math mode: the name of the net, or the expression of an internal net.
+fn gate_label<B: BitSet>(solution: &Solution<B>, gate: NetId) -> String {
+    let name = solution.net_name(gate);
+    if solution.nets[gate].origin != NetOrigin::Internal {
+        return math_name(name);
+    }
+    expression(solution, gate).unwrap_or_else(|| math_name(name))
+}
+
+/// The levels of `net` as a sum of products of the inputs and the power nets that are not supplies, or its
+/// complement if that has fewer literals or terms. Rows where the net or one of the variables is not driven are
+/// don't care. `None` if there are too many variables, or if the net has both levels for the same values of the
+/// variables.
+fn expression<B: BitSet>(solution: &Solution<B>, net: NetId) -> Option<String> {
+    let variables: Vec<NetId> = ports(solution).into_iter().filter(|&id| is_input(solution, id)).collect();
+    if variables.len() > MAX_VARIABLES { return None; }
+
+    let level = |bit: Bit| match bit {
+        Bit::S0 | Bit::W0 => Some(false),
+        Bit::S1 | Bit::W1 => Some(true),
+        Bit::Z | Bit::X => None,
+    };
+    let (mut on, mut off) = (vec![false; 1 << variables.len()], vec![false; 1 << variables.len()]);
+    let signal = &solution.nets[net].signal;
+    for row in 0..signal.rows() {
+        let pattern: Option<usize> = variables.iter().enumerate()
+            .map(|(i, &v)| level(solution.nets[v].signal.get_row(row)).map(|high| (high as usize) << i))
+            .sum();
+        if let (Some(pattern), Some(high)) = (pattern, level(signal.get_row(row))) {
+            if high { on[pattern] = true } else { off[pattern] = true }
+        }
+    }
+
+    if on.iter().zip(&off).any(|(&high, &low)| high && low) { return None; }
+    match (on.contains(&true), off.contains(&true)) {
+        (false, false) => return None,
+        (true, false) => return Some("1".to_string()),
+        (false, true) => return Some("0".to_string()),
+        (true, true) => {}
+    }
+
+    let names: Vec<String> = variables.iter().map(|&v| math_name(solution.net_name(v))).collect();
+    let size = |terms: &[(usize, usize)]| -> (usize, usize) {
+        let literals = terms.iter().map(|&(_, mask)| names.len() - mask.count_ones() as usize).sum();
+        (literals, terms.len())
+    };
+    let (positive, negative) = (sum_of_products(&on, &off), sum_of_products(&off, &on));
+    if size(&negative) < size(&positive) {
+        Some(format!("\\overline{{{}}}", format_terms(&negative, &names)))
+    } else {
+        Some(format_terms(&positive, &names))
+    }
+}
+
+/// The terms `(value, mask)` that cover the `on` patterns without any of the `off` patterns, where the bits in
+/// `mask` are the variables that don't appear in the term. The prime implicants are found by merging terms that
+/// differ in a single variable and then picked greedily, which is not always the smallest cover.
+fn sum_of_products(on: &[bool], off: &[bool]) -> Vec<(usize, usize)> {
+    let mut terms: Vec<(usize, usize)> = (0..on.len()).filter(|&p| !off[p]).map(|p| (p, 0)).collect();
+    let mut primes = Vec::new();
+    while !terms.is_empty() {
+        let mut merged = vec![false; terms.len()];
+        let mut next = Vec::new();
+        for i in 0..terms.len() {
+            for j in i + 1..terms.len() {
+                let ((a, mask), (b, other_mask)) = (terms[i], terms[j]);
+                if mask != other_mask || (a ^ b).count_ones() != 1 { continue; }
+                merged[i] = true;
+                merged[j] = true;
+                let term = (a & b, mask | (a ^ b));
+                if !next.contains(&term) {
+                    next.push(term);
+                }
+            }
+        }
+        primes.extend(terms.iter().zip(&merged).filter(|(_, &m)| !m).map(|(&term, _)| term));
+        terms = next;
+    }
+
+    let covers = |(value, mask): (usize, usize), pattern: usize| pattern & !mask == value;
+    let mut uncovered: Vec<usize> = (0..on.len()).filter(|&p| on[p]).collect();
+    let mut result = Vec::new();
+    while !uncovered.is_empty() {
+        let best = primes.iter().copied()
+            .max_by_key(|&term| (uncovered.iter().filter(|&&p| covers(term, p)).count(), term.1.count_ones()))
+            .unwrap();
+        uncovered.retain(|&p| !covers(best, p));
+        result.push(best);
+    }
+    result
+}
+
+fn format_terms(terms: &[(usize, usize)], names: &[String]) -> String {
+    let term = |&(value, mask): &(usize, usize)| -> String {
+        let literals: Vec<String> = (0..names.len()).filter(|&i| mask & (1 << i) == 0).map(|i| {
+            if value & (1 << i) != 0 { names[i].clone() } else { format!("\\overline{{{}}}", names[i]) }
+        }).collect();
+        literals.join("\\,")
+    };
+    terms.iter().map(term).collect::<Vec<_>>().join(" + ")
+}
+
+fn math_name(name: &str) -> String {
+    format!("\\mathit{{{}}}", name)
+}
+
+fn value(bit: Bit) -> &'static str {
+    match bit {
+        Bit::S0 => "0",
+        Bit::S1 => "1",
+        Bit::W0 => "L",
+        Bit::W1 => "H",
+        Bit::Z => "Z",
+        Bit::X => "X",
+    }
+}
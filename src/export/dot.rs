@@ -0,0 +1,83 @@
+use crate::export::{device_color, device_label, supply};
+use crate::signal::BitSet;
+use crate::solution::{Kind, NetId, NetOrigin, Solution};
+
+/// Write `solution` as a Graphviz digraph. Devices are nodes and internal nets are edges from the devices that
+/// drive them to the devices that use them, labeled with the net name. Power nets, inputs and outputs are nodes
+/// of their own, with the power nets that are high and low in every row at the top and bottom.
+/// Gate connections are dashed, the gate of a PMOS ends in a bubble.
+pub fn to_dot<B: BitSet>(solution: &Solution<B>, name: &str) -> String {
+    let mut lines = vec![format!("digraph {} {{", quote(name)), "    node [fontname=\"Helvetica\"];".to_string()];
+
+    let has_node = |net: NetId| solution.nets[net].origin != NetOrigin::Internal || solution.outputs.contains(&net);
+    for (_, net) in solution.nets.iter().enumerate().filter(|&(id, _)| has_node(id)) {
+        let shape = if net.origin == NetOrigin::Internal { "shape=box, style=rounded" } else { "shape=plaintext" };
+        lines.push(format!("    {} [{}];", quote(&net.name), shape));
+    }
+    for (high, rank) in [(true, "source"), (false, "sink")] {
+        if let Some(rail) = supply(solution, high) {
+            lines.push(format!("    {{rank={}; {};}}", rank, quote(solution.net_name(rail))));
+        }
+    }
+
+    for (i, device) in solution.devices.iter().enumerate() {
+        let style = if device.kind.has_gate() { "filled" } else { "\"filled,dashed\"" };
+        lines.push(format!(
+            "    {} [label=\"M{}\\n{}\", shape=box, style={}, fillcolor=\"{}\"];",
+            device_id(i), i + 1, device_label(device.kind), style, device_color(device.kind),
+        ));
+    }
+
+    //nets without a node of their own start at the devices that drive them
+    let from = |net: NetId| -> Vec<String> {
+        if has_node(net) {
+            vec![quote(solution.net_name(net))]
+        } else {
+            solution.nets[net].drivers.iter().map(|&d| device_id(d)).collect()
+        }
+    };
+    let mut edge = |net: NetId, to: &str, style: &str| {
+        let mut attributes = Vec::new();
+        if !has_node(net) {
+            attributes.push(format!("label={}", quote(solution.net_name(net))));
+        }
+        if !style.is_empty() {
+            attributes.push(style.to_string());
+        }
+        for from in from(net) {
+            if attributes.is_empty() {
+                lines.push(format!("    {} -> {};", from, to));
+            } else {
+                lines.push(format!("    {} -> {} [{}];", from, to, attributes.join(", ")));
+            }
+        }
+    };
+
+    for (i, device) in solution.devices.iter().enumerate() {
+        let id = device_id(i);
+        edge(device.source, &id, "");
+        if let Some(gate) = device.gate {
+            edge(gate, &id, if device.kind == Kind::PMOS { "style=dashed, arrowhead=odot" } else { "style=dashed" });
+        }
+        if let Some(inverted_gate) = device.inverted_gate {
+            edge(inverted_gate, &id, "style=dashed, arrowhead=odot");
+        }
+    }
+    for (id, net) in solution.nets.iter().enumerate() {
+        if net.origin != NetOrigin::Internal || !has_node(id) { continue; }
+        for &driver in &net.drivers {
+            lines.push(format!("    {} -> {};", device_id(driver), quote(&net.name)));
+        }
+    }
+
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+fn device_id(device: usize) -> String {
+    format!("m{}", device + 1)
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\\\""))
+}
@@ -0,0 +1,120 @@
+use crate::bit::Bit;
+use crate::export::layout::{Layout, MARGIN};
+use crate::export::{ports, supply_level};
+use crate::signal::BitSet;
+use crate::solution::{Kind, NetId, NetOrigin, Solution};
+
+/// the voltage of the rails and of a high logic input
+const VDD: f64 = 5.0;
+/// the distance from the channel of a transistor to its source and drain posts and to its gate post
+const POST: i32 = 16;
+const GATE: i32 = 32;
+/// the gain of normal transistors and of the weak always-on transistors of pull devices
+const BETA: f64 = 0.02;
+const WEAK_BETA: f64 = 0.002;
+const RESISTANCE: f64 = 100_000.0;
+/// the vertical distance between the ports below the circuit
+const PORT_SPACING: i32 = 32;
+
+/// Write `solution` in the text import format of the Falstad circuit simulator, with the devices in the same
+/// places as `svg::to_svg`. Every net is connected through labeled nodes with its name. Below the circuit the
+/// inputs and the power nets that change between rows get a logic input switch that starts at row 0, the power
+/// nets that are high or low in every row become a rail or ground, and every output gets a logic probe.
+pub fn to_falstad<B: BitSet>(solution: &Solution<B>) -> String {
+    let layout = Layout::new(solution);
+    let mut lines = vec!["$ 1 0.000005 10.20027730826997 50 5 50".to_string()];
+    let label = |x: i32, y: i32, dx: i32, net: NetId| {
+        format!("207 {} {} {} {} 0 {}", x, y, x + dx, y, solution.net_name(net))
+    };
+
+    //buses, split at every terminal so the wires from the terminals end on a wire end
+    for id in 0..solution.nets.len() {
+        let (y, (start, end)) = match layout.bus[id] {
+            Some(bus) => (layout.net_y[id], bus),
+            None => continue,
+        };
+        let mut xs: Vec<i32> = layout.terminals[id].iter().map(|t| t.0).collect();
+        xs.extend([start, end]);
+        xs.sort_unstable();
+        xs.dedup();
+        lines.push(label(start, y, -POST, id));
+        for pair in xs.windows(2) {
+            lines.push(wire(pair[0], y, pair[1], y));
+        }
+    }
+
+    for (i, device) in solution.devices.iter().enumerate() {
+        let (x, y) = layout.device_pos[i];
+
+        //the posts are closer to the center than the terminals of the layout
+        let (source_y, drain_y) = layout.terminal_y[i];
+        for (net, terminal_y) in [(device.source, source_y), (device.drain, drain_y)] {
+            let post = if terminal_y < y { y - POST } else { y + POST };
+            lines.push(wire(x, post, x, layout.net_y[net]));
+        }
+
+        match device.kind {
+            Kind::Resistor => lines.push(format!("r {} {} {} {} 0 {}", x, y - POST, x, y + POST, RESISTANCE)),
+            Kind::PullUp => {
+                lines.push(mosfet(x, y, -GATE, true, WEAK_BETA));
+                lines.push(ground(x - GATE, y));
+            }
+            Kind::PullDown => {
+                lines.push(mosfet(x, y, -GATE, false, WEAK_BETA));
+                lines.push(rail(x - GATE, y));
+            }
+            _ => {
+                let gate = device.gate.expect("transistor without gate");
+                lines.push(mosfet(x, y, -GATE, device.kind == Kind::PMOS, BETA));
+                lines.push(label(x - GATE, y, -POST, gate));
+                if let Some(inverted_gate) = device.inverted_gate {
+                    lines.push(mosfet(x, y, GATE, true, BETA));
+                    lines.push(label(x + GATE, y, POST, inverted_gate));
+                }
+            }
+        }
+    }
+
+    let (x, mut y) = (MARGIN + GATE, layout.height);
+    for id in ports(solution) {
+        let net = &solution.nets[id];
+        let port = match (net.origin, supply_level(&net.signal)) {
+            (NetOrigin::Internal, _) => {
+                lines.push(format!("M {} {} {} {} 0 2.5", x + 2 * GATE, y, x + 3 * GATE, y));
+                lines.push(label(x + 2 * GATE, y, -GATE, id));
+                y += PORT_SPACING;
+                continue;
+            }
+            (NetOrigin::Power(_), Some(true)) => rail(x, y),
+            (NetOrigin::Power(_), Some(false)) => ground(x, y),
+            _ => {
+                let position = if matches!(net.signal.get_row(0), Bit::S1 | Bit::W1) { 1 } else { 0 };
+                format!("L {} {} {} {} 0 {} false {} 0", x, y, x - GATE, y, position, VDD)
+            }
+        };
+        lines.push(port);
+        lines.push(label(x, y, GATE, id));
+        y += PORT_SPACING;
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// A MOSFET with its channel at `(x, y)`, the gate post `gate_dx` to the side and the source and drain posts
+/// `POST` above and below the channel.
+fn mosfet(x: i32, y: i32, gate_dx: i32, pmos: bool, beta: f64) -> String {
+    format!("f {} {} {} {} {} 1.5 {}", x + gate_dx, y, x, y, pmos as u8, beta)
+}
+
+/// A DC rail at `VDD` with its post at `(x, y)`.
+fn rail(x: i32, y: i32) -> String {
+    format!("R {} {} {} {} 0 0 40 {} 0 0 0.5", x, y, x, y - POST, VDD)
+}
+
+fn ground(x: i32, y: i32) -> String {
+    format!("g {} {} {} {} 0 0", x, y, x, y + POST)
+}
+
+fn wire(x1: i32, y1: i32, x2: i32, y2: i32) -> String {
+    format!("w {} {} {} {} 0", x1, y1, x2, y2)
+}
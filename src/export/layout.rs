@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use crate::export::supply_level;
+use crate::signal::BitSet;
+use crate::solution::{Net, NetId, NetOrigin, Solution};
+
+pub(crate) const MARGIN: i32 = 30;
+/// space left of the first column for the names of the nets
+const LABELS: i32 = 50;
+/// where the buses of the nets start, right of their names
+const BUS_START: i32 = MARGIN + LABELS - 10;
+const COLUMN: i32 = 90;
+const ROW: i32 = 80;
+/// distance between power nets on the same side
+const RAIL: i32 = 16;
+/// distance between internal nets that would otherwise be at the same height
+const BUS_OFFSET: i32 = 8;
+pub(crate) const DEVICE_WIDTH: i32 = 30;
+pub(crate) const DEVICE_HEIGHT: i32 = 40;
+
+/// Which network a device or net belongs to: the pull-up network hangs from the power nets at the top, the
+/// pull-down network from the power nets at the bottom that are low in every row.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Side {
+    Up,
+    Down,
+}
+
+/// Where the devices and nets of a solution go in a drawing, in the classic CMOS arrangement described in
+/// `svg::to_svg`. The devices are `DEVICE_WIDTH` by `DEVICE_HEIGHT` with a terminal in the middle of the top and
+/// bottom edges.
+pub(crate) struct Layout {
+    /// the center of each device
+    pub(crate) device_pos: Vec<(i32, i32)>,
+    /// the height of the bus of each net
+    pub(crate) net_y: Vec<i32>,
+    /// the source and drain terminals connected to each net
+    pub(crate) terminals: Vec<Vec<(i32, i32)>>,
+    /// the height of the source and drain terminal of each device
+    pub(crate) terminal_y: Vec<(i32, i32)>,
+    /// the start and end of the bus of each net, power nets span the whole width and the other nets end at their
+    /// last terminal. `None` for nets that are only connected to gates.
+    pub(crate) bus: Vec<Option<(i32, i32)>>,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+}
+
+impl Layout {
+    pub(crate) fn new<B: BitSet>(solution: &Solution<B>) -> Layout {
+        let nets = &solution.nets;
+        let is_low = |net: &Net<B>| net.origin != NetOrigin::Internal && supply_level(&net.signal) == Some(false);
+
+        //a device hangs from its source, a net is as deep as the deepest device driving it
+        let mut net_side: Vec<Side> = nets.iter().map(|net| if is_low(net) { Side::Down } else { Side::Up }).collect();
+        let mut net_depth = vec![0; nets.len()];
+        let mut device_depth = Vec::new();
+        for device in &solution.devices {
+            let depth = net_depth[device.source] + 1;
+            if nets[device.drain].drivers[0] == device_depth.len() {
+                net_side[device.drain] = net_side[device.source];
+            }
+            net_depth[device.drain] = net_depth[device.drain].max(depth);
+            device_depth.push(depth);
+        }
+        let device_side: Vec<Side> = solution.devices.iter().map(|d| net_side[d.source]).collect();
+
+        let max_depth = |side: Side| {
+            device_depth.iter().zip(&device_side).filter(|(_, &s)| s == side).map(|(&d, _)| d).max().unwrap_or(0)
+        };
+        let power = |side: Side| -> Vec<NetId> {
+            let is_power = |id: NetId| matches!(nets[id].origin, NetOrigin::Power(_));
+            (0..nets.len()).filter(|&id| is_power(id) && net_side[id] == side).collect()
+        };
+        let (up_power, down_power) = (power(Side::Up), power(Side::Down));
+
+        let top = MARGIN + RAIL * (up_power.len().max(1) as i32 - 1);
+        let middle = top + ROW * max_depth(Side::Up) + ROW / 2;
+        let bottom = middle + ROW / 2 + ROW * max_depth(Side::Down);
+
+        let mut net_y = vec![0; nets.len()];
+        for (id, net) in nets.iter().enumerate() {
+            let rail = |power: &[NetId]| RAIL * power.iter().position(|&p| p == id).unwrap() as i32;
+            net_y[id] = match (net.origin, net_side[id]) {
+                (NetOrigin::Power(_), Side::Up) => MARGIN + rail(&up_power),
+                (NetOrigin::Power(_), Side::Down) => bottom + rail(&down_power),
+                _ if solution.outputs.contains(&id) => middle,
+                (_, Side::Up) => top + ROW * net_depth[id],
+                (_, Side::Down) => bottom - ROW * net_depth[id],
+            };
+        }
+
+        //alternate below and above the usual height, so the buses don't overlap
+        let mut used: BTreeMap<i32, i32> = BTreeMap::new();
+        for (id, net) in nets.iter().enumerate() {
+            if net.origin != NetOrigin::Internal { continue; }
+            let count = used.entry(net_y[id]).or_insert(0);
+            let offset = BUS_OFFSET * ((*count + 1) / 2);
+            net_y[id] += if *count % 2 == 1 { offset } else { -offset };
+            *count += 1;
+        }
+
+        //devices in the same row are placed next to each other in the order they were built
+        let mut columns: BTreeMap<(bool, i32), i32> = BTreeMap::new();
+        let mut device_pos = Vec::new();
+        let mut terminals = vec![Vec::new(); nets.len()];
+        let mut terminal_y = Vec::new();
+        for (i, device) in solution.devices.iter().enumerate() {
+            let column = columns.entry((device_side[i] == Side::Up, device_depth[i])).or_insert(0);
+            let x = MARGIN + LABELS + COLUMN * *column + COLUMN / 2;
+            *column += 1;
+
+            let (y, source_y, drain_y) = match device_side[i] {
+                Side::Up => {
+                    let y = top + ROW * (device_depth[i] - 1) + ROW / 2;
+                    (y, y - DEVICE_HEIGHT / 2, y + DEVICE_HEIGHT / 2)
+                }
+                Side::Down => {
+                    let y = bottom - ROW * (device_depth[i] - 1) - ROW / 2;
+                    (y, y + DEVICE_HEIGHT / 2, y - DEVICE_HEIGHT / 2)
+                }
+            };
+            device_pos.push((x, y));
+            terminal_y.push((source_y, drain_y));
+            terminals[device.source].push((x, source_y));
+            terminals[device.drain].push((x, drain_y));
+        }
+
+        let columns = columns.values().copied().max().unwrap_or(0).max(1);
+        let width = 2 * MARGIN + LABELS + COLUMN * columns;
+        let bus = nets.iter().zip(&terminals).map(|(net, terminals)| {
+            if matches!(net.origin, NetOrigin::Power(_)) {
+                Some((BUS_START, width - MARGIN))
+            } else {
+                terminals.iter().map(|t| t.0).max().map(|end| (BUS_START, end))
+            }
+        }).collect();
+        Layout {
+            device_pos,
+            net_y,
+            terminals,
+            terminal_y,
+            bus,
+            width,
+            height: bottom + RAIL * (down_power.len().max(1) as i32 - 1) + MARGIN,
+        }
+    }
+}
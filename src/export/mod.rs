@@ -0,0 +1,91 @@
+use std::fmt::{Display, Error, Formatter};
+
+use crate::signal::{BitSet, Signal};
+use crate::solution::{Kind, NetId, NetOrigin, Solution};
+
+pub mod circuitikz;
+pub mod dot;
+pub mod falstad;
+mod layout;
+pub mod spice;
+pub mod svg;
+pub mod verilog;
+
+/// Reasons a solution can't be exported to a particular format.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExportError {
+    /// the format needs a power net that is high (or low) in every row, but the query doesn't have one
+    MissingSupply { high: bool },
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            ExportError::MissingSupply { high } =>
+                write!(f, "No power signal that is {} in every row", if high { "high" } else { "low" }),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// The power net that is strongly driven to `high` in every row, if there is one.
+pub fn supply<B: BitSet>(solution: &Solution<B>, high: bool) -> Option<NetId> {
+    solution.nets.iter().position(|net| {
+        matches!(net.origin, NetOrigin::Power(_)) && supply_level(&net.signal) == Some(high)
+    })
+}
+
+/// The level of a signal that is strongly driven to the same level in every row, like a supply.
+pub(crate) fn supply_level<B: BitSet>(signal: &Signal<B>) -> Option<bool> {
+    [true, false].iter().copied().find(|&level| *signal == Signal::constant(signal.rows(), level))
+}
+
+/// Whether the net is driven from outside like an input: a query input or a power net that is not a supply.
+pub(crate) fn is_input<B: BitSet>(solution: &Solution<B>, id: NetId) -> bool {
+    let net = &solution.nets[id];
+    match net.origin {
+        NetOrigin::Input(_) => true,
+        NetOrigin::Power(_) => supply_level(&net.signal).is_none(),
+        NetOrigin::Internal => false,
+    }
+}
+
+/// A short name for the kind of a device in drawings.
+pub fn device_label(kind: Kind) -> &'static str {
+    match kind {
+        Kind::PMOS => "PMOS",
+        Kind::NMOS => "NMOS",
+        Kind::TransmissionGate => "TG",
+        Kind::Resistor => "R",
+        Kind::PullUp => "PU",
+        Kind::PullDown => "PD",
+    }
+}
+
+/// The fill color of a device in drawings: red for the pull-up kinds, blue for the pull-down kinds.
+pub fn device_color(kind: Kind) -> &'static str {
+    match kind {
+        Kind::PMOS | Kind::PullUp => "#f8cecc",
+        Kind::NMOS | Kind::PullDown => "#dae8fc",
+        Kind::TransmissionGate => "#e1d5e7",
+        Kind::Resistor => "#eeeeee",
+    }
+}
+
+/// The nets that connect the circuit to the outside: the inputs, the outputs and the power nets, in that order.
+/// An output that is implemented by the same net as an earlier one is only listed once.
+pub fn ports<B: BitSet>(solution: &Solution<B>) -> Vec<NetId> {
+    let origin = |id: &NetId| solution.nets[*id].origin;
+    let inputs = (0..solution.nets.len()).filter(|id| matches!(origin(id), NetOrigin::Input(_)));
+    let power = (0..solution.nets.len()).filter(|id| matches!(origin(id), NetOrigin::Power(_)));
+
+    let mut result: Vec<NetId> = inputs.collect();
+    for &output in &solution.outputs {
+        if !result.contains(&output) {
+            result.push(output);
+        }
+    }
+    result.extend(power);
+    result
+}
@@ -0,0 +1,179 @@
+use crate::bit::Bit;
+use crate::export::{ports, supply, ExportError};
+use crate::signal::BitSet;
+use crate::solution::{Device, Kind, NetOrigin, Solution};
+
+/// The model name and size of a transistor. `w` and `l` are written as is, so they can use SPICE suffixes like
+/// `180n`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MosModel {
+    pub model: String,
+    pub w: String,
+    pub l: String,
+}
+
+impl MosModel {
+    pub fn new(model: &str, w: &str, l: &str) -> MosModel {
+        MosModel { model: model.to_string(), w: w.to_string(), l: l.to_string() }
+    }
+}
+
+/// How a solution is written as a SPICE netlist, the models are chosen per `Kind`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpiceOptions {
+    /// the name of the `.subckt`
+    pub name: String,
+    pub pmos: MosModel,
+    pub nmos: MosModel,
+    /// the two halves of a transmission gate
+    pub transmission_gate_pmos: MosModel,
+    pub transmission_gate_nmos: MosModel,
+    /// the always-on transistors of `Kind::PullUp` and `Kind::PullDown`, weak because of their long channel
+    pub pull_up: MosModel,
+    pub pull_down: MosModel,
+    /// the value of every `Kind::Resistor`
+    pub resistance: String,
+    /// Also write a transient testbench after the subcircuit.
+    pub testbench: Option<Testbench>,
+}
+
+impl Default for SpiceOptions {
+    fn default() -> Self {
+        SpiceOptions {
+            name: "cell".to_string(),
+            pmos: MosModel::new("pmos", "2u", "180n"),
+            nmos: MosModel::new("nmos", "1u", "180n"),
+            transmission_gate_pmos: MosModel::new("pmos", "2u", "180n"),
+            transmission_gate_nmos: MosModel::new("nmos", "1u", "180n"),
+            pull_up: MosModel::new("pmos", "500n", "2u"),
+            pull_down: MosModel::new("nmos", "500n", "2u"),
+            resistance: "100k".to_string(),
+            testbench: None,
+        }
+    }
+}
+
+/// A testbench that drives every input and power net with the rows of its signal one after the other, starting
+/// with row 0.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Testbench {
+    /// the voltage of a high level
+    pub vdd: f64,
+    /// how long each row is applied, in ns
+    pub period_ns: f64,
+    /// the time it takes to switch to the next row, in ns
+    pub rise_ns: f64,
+}
+
+impl Default for Testbench {
+    fn default() -> Self {
+        Testbench { vdd: 1.8, period_ns: 10.0, rise_ns: 0.1 }
+    }
+}
+
+/// Write `solution` as a `.subckt` with the ports from `export::ports`. The bulk of a PMOS is connected to the
+/// power net that is high in every row and the bulk of an NMOS to the one that is low, falling back to the source
+/// and the global ground. The gate of a pull-down needs a high power net.
+pub fn to_spice<B: BitSet>(solution: &Solution<B>, options: &SpiceOptions) -> Result<String, ExportError> {
+    let name = |net| solution.net_name(net).to_string();
+    let vdd = supply(solution, true).map(name);
+    let gnd = supply(solution, false).map_or("0".to_string(), name);
+
+    let ports: Vec<String> = ports(solution).into_iter().map(name).collect();
+    let mut lines = vec![
+        format!("* {}: {} devices", options.name, solution.device_count()),
+        format!(".subckt {} {}", options.name, ports.join(" ")),
+    ];
+
+    let (mut transistors, mut resistors) = (0, 0);
+    for device in &solution.devices {
+        if device.kind == Kind::Resistor {
+            resistors += 1;
+            lines.push(format!(
+                "R{} {} {} {}", resistors, name(device.drain), name(device.source), options.resistance,
+            ));
+            continue;
+        }
+
+        for transistor in device.transistors() {
+            let (model, gate) = match device.kind {
+                Kind::PullUp => (&options.pull_up, gnd.clone()),
+                Kind::PullDown => {
+                    (&options.pull_down, vdd.clone().ok_or(ExportError::MissingSupply { high: true })?)
+                }
+                _ => (model(options, device, &transistor), name(transistor.gate.expect("transistor without gate"))),
+            };
+            let bulk = match transistor.kind {
+                Kind::PMOS | Kind::PullUp => vdd.clone().unwrap_or_else(|| name(transistor.source)),
+                _ => gnd.clone(),
+            };
+
+            transistors += 1;
+            let (drain, source) = (name(transistor.drain), name(transistor.source));
+            lines.push(format!(
+                "M{} {} {} {} {} {} W={} L={}", transistors, drain, gate, source, bulk, model.model, model.w, model.l,
+            ));
+        }
+    }
+    lines.push(format!(".ends {}", options.name));
+
+    if let Some(testbench) = &options.testbench {
+        lines.push(String::new());
+        lines.extend(testbench_lines(solution, &options.name, &ports, testbench));
+    }
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// The model of a transistor of `device`, which is a PMOS, an NMOS or a transmission gate.
+fn model<'o>(options: &'o SpiceOptions, device: &Device, transistor: &Device) -> &'o MosModel {
+    match (device.kind, transistor.kind) {
+        (Kind::TransmissionGate, Kind::PMOS) => &options.transmission_gate_pmos,
+        (Kind::TransmissionGate, _) => &options.transmission_gate_nmos,
+        (_, Kind::PMOS) => &options.pmos,
+        _ => &options.nmos,
+    }
+}
+
+fn testbench_lines<B: BitSet>(
+    solution: &Solution<B>, name: &str, ports: &[String], testbench: &Testbench,
+) -> Vec<String> {
+    let rows = solution.nets.first().map_or(0, |net| net.signal.rows());
+    let mut lines = vec![
+        format!("* testbench, row i of the inputs is applied from i * {}ns", number(testbench.period_ns)),
+        format!("X{} {} {}", name, ports.join(" "), name),
+    ];
+
+    for net in solution.nets.iter().filter(|net| net.origin != NetOrigin::Internal) {
+        let voltage = |row| if matches!(net.signal.get_row(row), Bit::S1 | Bit::W1) { testbench.vdd } else { 0.0 };
+
+        let source = if (1..rows).all(|row| voltage(row) == voltage(0)) {
+            number(voltage(0))
+        } else {
+            let mut points = vec![(0.0, voltage(0))];
+            for row in 1..rows {
+                let start = row as f64 * testbench.period_ns;
+                points.push((start, voltage(row - 1)));
+                points.push((start + testbench.rise_ns, voltage(row)));
+            }
+            let points: Vec<String> = points.iter().map(|&(t, v)| format!("{}n {}", number(t), number(v))).collect();
+            format!("PWL({})", points.join(" "))
+        };
+        lines.push(format!("V{} {} 0 {}", net.name, net.name, source));
+    }
+
+    for &output in &solution.outputs {
+        let net = &solution.nets[output];
+        lines.push(format!("* expected {} = {:?}, the last row first", net.name, net.signal));
+    }
+    let stop = rows as f64 * testbench.period_ns;
+    lines.push(format!(".tran {}n {}n", number(testbench.period_ns / 100.0), number(stop)));
+    lines.push(".end".to_string());
+    lines
+}
+
+/// Format a number without trailing zeros and rounding noise.
+fn number(x: f64) -> String {
+    let s = format!("{:.6}", x);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
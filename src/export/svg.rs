@@ -0,0 +1,83 @@
+use crate::export::{device_color, device_label};
+use crate::export::layout::{Layout, DEVICE_HEIGHT, DEVICE_WIDTH};
+use crate::signal::BitSet;
+use crate::solution::{Kind, NetOrigin, Solution};
+
+/// Draw `solution` as an SVG schematic in the classic CMOS arrangement, without needing Graphviz. Devices are
+/// placed in rows by the number of devices between them and their power net, the pull-up network below the
+/// power nets at the top and the pull-down network above the power nets at the bottom, with the outputs in
+/// between. Every net except the gates is a horizontal bus, gates are labeled with the name of their net.
+pub fn to_svg<B: BitSet>(solution: &Solution<B>, name: &str) -> String {
+    let layout = Layout::new(solution);
+    let mut body = Vec::new();
+
+    //buses, with the wires from the device terminals to them
+    for (id, net) in solution.nets.iter().enumerate() {
+        let (y, (start, end)) = match layout.bus[id] {
+            Some(bus) => (layout.net_y[id], bus),
+            None => continue,
+        };
+        let is_rail = matches!(net.origin, NetOrigin::Power(_));
+        body.push(line(start, y, end, y, if is_rail { 2 } else { 1 }));
+        body.push(text(start - 4, y + 4, "end", &net.name));
+        for &(x, terminal_y) in &layout.terminals[id] {
+            body.push(line(x, terminal_y, x, y, 1));
+            body.push(format!("<circle cx=\"{}\" cy=\"{}\" r=\"2.5\"/>", x, y));
+        }
+    }
+
+    for (i, device) in solution.devices.iter().enumerate() {
+        let (x, y) = layout.device_pos[i];
+        let left = x - DEVICE_WIDTH / 2;
+        body.push(format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"black\"/>",
+            left, y - DEVICE_HEIGHT / 2, DEVICE_WIDTH, DEVICE_HEIGHT, device_color(device.kind),
+        ));
+        body.push(format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"9\">{}</text>",
+            x, y + 3, device_label(device.kind)));
+
+        if let Some(gate) = device.gate {
+            let mut label = solution.net_name(gate).to_string();
+            if let Some(inverted_gate) = device.inverted_gate {
+                label = format!("{}/{}", label, solution.net_name(inverted_gate));
+            }
+            if device.kind == Kind::PMOS {
+                body.push(format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"white\" stroke=\"black\"/>", left - 3, y,
+                ));
+                body.push(line(left - 12, y, left - 6, y, 1));
+            } else {
+                body.push(line(left - 12, y, left, y, 1));
+            }
+            body.push(text(left - 14, y + 4, "end", &label));
+        }
+    }
+
+    let mut lines = vec![
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+            font-family=\"Helvetica, sans-serif\" font-size=\"12\" stroke-linecap=\"round\">",
+            w = layout.width, h = layout.height,
+        ),
+        format!("<title>{}</title>", escape(name)),
+        "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>".to_string(),
+    ];
+    lines.extend(body);
+    lines.push("</svg>".to_string());
+    lines.join("\n") + "\n"
+}
+
+fn line(x1: i32, y1: i32, x2: i32, y2: i32, width: i32) -> String {
+    format!(
+        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"{}\"/>",
+        x1, y1, x2, y2, width,
+    )
+}
+
+fn text(x: i32, y: i32, anchor: &str, s: &str) -> String {
+    format!("<text x=\"{}\" y=\"{}\" text-anchor=\"{}\">{}</text>", x, y, anchor, escape(s))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,125 @@
+use crate::bit::Bit;
+use crate::export::{is_input, ports, supply_level};
+use crate::signal::{BitSet, Query, Signal};
+use crate::solution::{Kind, NetId, NetOrigin, Solution};
+
+/// Write `solution` as a switch-level Verilog module. Power nets that are high or low in every row become
+/// `supply1` and `supply0` nets inside the module, the other power nets are inputs like the query inputs.
+/// Transmission gates are a single `tranif1`, resistors and pull devices are always-on resistive switches.
+pub fn to_verilog<B: BitSet>(solution: &Solution<B>, name: &str) -> String {
+    let net_name = |net: NetId| solution.net_name(net);
+    let mut lines = header(solution, name);
+
+    for (id, net) in solution.nets.iter().enumerate() {
+        let declaration = match (net.origin, supply_level(&net.signal)) {
+            (NetOrigin::Internal, _) if can_float(&net.signal) => "tri",
+            (NetOrigin::Internal, _) => "wire",
+            (NetOrigin::Power(_), Some(true)) => "supply1",
+            (NetOrigin::Power(_), Some(false)) => "supply0",
+            _ => continue,
+        };
+        lines.push(format!("    {} {};", declaration, net_name(id)));
+    }
+    lines.push(String::new());
+
+    for (i, device) in solution.devices.iter().enumerate() {
+        let (drain, source) = (net_name(device.drain), net_name(device.source));
+        let gate = device.gate.map(net_name);
+        let line = match (device.kind, gate) {
+            (Kind::PMOS, Some(gate)) => format!("pmos m{}({}, {}, {});", i + 1, drain, source, gate),
+            (Kind::NMOS, Some(gate)) => format!("nmos m{}({}, {}, {});", i + 1, drain, source, gate),
+            (Kind::TransmissionGate, Some(gate)) => format!("tranif1 m{}({}, {}, {});", i + 1, drain, source, gate),
+            (Kind::PullUp, _) => format!("rpmos m{}({}, {}, 1'b0);", i + 1, drain, source),
+            _ => format!("rnmos m{}({}, {}, 1'b1);", i + 1, drain, source),
+        };
+        lines.push(format!("    {}", line));
+    }
+    lines.push("endmodule".to_string());
+
+    lines.join("\n") + "\n"
+}
+
+/// Write a behavioural Verilog module with the same ports as `to_verilog`, built from the truth tables of the
+/// outputs in `query` instead of the devices, so the two can be compared in a simulator. Rows that are don't
+/// care give `x`, the strength of the outputs is not modeled.
+pub fn behavioural_model<B: BitSet>(solution: &Solution<B>, query: &Query<B>, name: &str) -> String {
+    let mut lines = header(solution, name);
+
+    let outputs: Vec<NetId> = ports(solution).into_iter().filter(|id| solution.outputs.contains(id)).collect();
+    lines.push(format!("    reg {};", names(solution, &outputs)));
+    lines.push(String::new());
+
+    let selectors: Vec<NetId> = ports(solution).into_iter().filter(|&id| is_input(solution, id)).collect();
+    let rows = query.rows();
+
+    lines.push("    always @(*) begin".to_string());
+    for output in outputs {
+        let index = solution.outputs.iter().position(|&o| o == output).unwrap();
+        let care_signal = &query.outputs[index];
+        let value = |row: usize| {
+            if !care_signal.care.get(row) || care_signal.any_strong.get(row) {
+                'x'
+            } else {
+                value_char(care_signal.signal.get_row(row))
+            }
+        };
+        let output = solution.net_name(output);
+
+        if selectors.is_empty() {
+            lines.push(format!("        {} = 1'b{};", output, value(0)));
+            continue;
+        }
+
+        lines.push(format!("        case ({{{}}})", names(solution, &selectors)));
+        let mut seen = Vec::new();
+        for row in 0..rows {
+            let pattern: String = selectors.iter()
+                .map(|&id| value_char(solution.nets[id].signal.get_row(row)))
+                .collect();
+            if seen.contains(&pattern) { continue; }
+            lines.push(format!("            {}'b{}: {} = 1'b{};", selectors.len(), pattern, output, value(row)));
+            seen.push(pattern);
+        }
+        lines.push(format!("            default: {} = 1'bx;", output));
+        lines.push("        endcase".to_string());
+    }
+    lines.push("    end".to_string());
+    lines.push("endmodule".to_string());
+
+    lines.join("\n") + "\n"
+}
+
+/// The module declaration with the ports and their directions.
+fn header<B: BitSet>(solution: &Solution<B>, name: &str) -> Vec<String> {
+    let ports: Vec<NetId> = ports(solution).into_iter()
+        .filter(|&id| solution.outputs.contains(&id) || is_input(solution, id))
+        .collect();
+    let (outputs, inputs): (Vec<NetId>, Vec<NetId>) = ports.iter().partition(|id| solution.outputs.contains(id));
+
+    let mut lines = vec![
+        format!("// {}: {} devices", name, solution.device_count()),
+        format!("module {}({});", name, names(solution, &ports)),
+    ];
+    if !inputs.is_empty() {
+        lines.push(format!("    input {};", names(solution, &inputs)));
+    }
+    lines.push(format!("    output {};", names(solution, &outputs)));
+    lines
+}
+
+fn can_float<B: BitSet>(signal: &Signal<B>) -> bool {
+    (0..signal.rows()).any(|row| signal.get_row(row) == Bit::Z)
+}
+
+fn value_char(bit: Bit) -> char {
+    match bit {
+        Bit::S0 | Bit::W0 => '0',
+        Bit::S1 | Bit::W1 => '1',
+        Bit::Z => 'z',
+        Bit::X => 'x',
+    }
+}
+
+fn names<B: BitSet>(solution: &Solution<B>, nets: &[NetId]) -> String {
+    nets.iter().map(|&id| solution.net_name(id)).collect::<Vec<_>>().join(", ")
+}
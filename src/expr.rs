@@ -0,0 +1,367 @@
+use std::fmt::{Display, Error, Formatter};
+
+use crate::signal::{BitSet, CareSignal, Query, Signal};
+
+/// A query built from Boolean expressions, owns the signals that `Query` borrows.
+#[derive(Debug, Clone)]
+pub struct ExprQuery<B: BitSet> {
+    pub input_names: Vec<String>,
+    pub output_names: Vec<String>,
+    /// vdd and gnd
+    pub power: Vec<Signal<B>>,
+    pub inputs: Vec<Signal<B>>,
+    pub outputs: Vec<CareSignal<B>>,
+}
+
+impl<B: BitSet> ExprQuery<B> {
+    /**
+    Build a query with the given inputs and one output for each `name = expression` line in `outputs`,
+    lines can also be separated by `;`.
+
+    Expressions use the inputs, `0`, `1`, `Z` for high impedance and `X` for don't care,
+    combined with `!` (or `~`), `&`, `^`, `|` and `c ? a : b` from high to low precedence.
+    Boolean operators on `Z` or `X` give `X`, unless the other side decides the result.
+
+    The input columns count up from all zeroes on the left to all ones on the right, with the first input as
+    the most significant bit. For `a, b` that's `a = "0011"` and `b = "0101"`.
+    */
+    pub fn parse(inputs: &[&str], outputs: &str) -> Result<ExprQuery<B>, ExprError> {
+        let mut names: Vec<&str> = Vec::new();
+        for &name in inputs {
+            if !is_identifier(name) || names.contains(&name) {
+                return Err(ExprError::BadName { name: name.to_string() });
+            }
+            names.push(name);
+        }
+
+        if inputs.len() >= usize::BITS as usize || 1 << inputs.len() > B::size() {
+            return Err(ExprError::TooManyInputs { inputs: inputs.len(), max_rows: B::size() });
+        }
+        let rows = 1 << inputs.len();
+
+        let mut parser = Parser { tokens: tokenize(outputs)?, index: 0, inputs };
+        let mut output_names = Vec::new();
+        let mut output_signals = Vec::new();
+        while let Some((name, expr)) = parser.line()? {
+            if names.contains(&name.as_str()) || output_names.contains(&name) {
+                return Err(ExprError::BadName { name });
+            }
+            output_signals.push(output_signal(&expr, inputs.len(), rows));
+            output_names.push(name);
+        }
+
+        if output_names.is_empty() {
+            return Err(ExprError::NoOutputs);
+        }
+
+        let input_signals = (0..inputs.len()).map(|i| {
+            let high: B = column(rows, |values| values[i]);
+            Signal::new(rows, !high.clone(), high, B::ones(rows))
+        }).collect();
+
+        Ok(ExprQuery {
+            input_names: inputs.iter().map(|s| s.to_string()).collect(),
+            output_names,
+            power: vec![Signal::constant(rows, true), Signal::constant(rows, false)],
+            inputs: input_signals,
+            outputs: output_signals,
+        })
+    }
+
+    pub fn query(&self) -> Query<'_, B> {
+        Query { power: &self.power, inputs: &self.inputs, outputs: &self.outputs }
+    }
+}
+
+/// The value of an expression in a single row.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Value {
+    Low,
+    High,
+    Z,
+    DontCare,
+}
+
+impl Value {
+    fn level(b: bool) -> Value {
+        if b { Value::High } else { Value::Low }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Input(usize),
+    Const(Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Xor(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Select(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, inputs: &[bool]) -> Value {
+        use Value::*;
+        match self {
+            Expr::Input(i) => Value::level(inputs[*i]),
+            Expr::Const(v) => *v,
+            Expr::Not(a) => match a.eval(inputs) {
+                Low => High,
+                High => Low,
+                _ => DontCare,
+            },
+            Expr::And(a, b) => match (a.eval(inputs), b.eval(inputs)) {
+                (Low, _) | (_, Low) => Low,
+                (High, High) => High,
+                _ => DontCare,
+            },
+            Expr::Or(a, b) => match (a.eval(inputs), b.eval(inputs)) {
+                (High, _) | (_, High) => High,
+                (Low, Low) => Low,
+                _ => DontCare,
+            },
+            Expr::Xor(a, b) => match (a.eval(inputs), b.eval(inputs)) {
+                (a @ (Low | High), b @ (Low | High)) => Value::level(a != b),
+                _ => DontCare,
+            },
+            Expr::Select(c, a, b) => match c.eval(inputs) {
+                High => a.eval(inputs),
+                Low => b.eval(inputs),
+                _ => match (a.eval(inputs), b.eval(inputs)) {
+                    (a, b) if a == b => a,
+                    _ => DontCare,
+                },
+            },
+        }
+    }
+}
+
+/// A bitset with the rows set where `f` is true, given the input values of that row.
+fn column<B: BitSet>(rows: usize, f: impl Fn(&[bool]) -> bool) -> B {
+    let inputs = rows.trailing_zeros() as usize;
+    let mut result = B::zero();
+    for row in 0..rows {
+        //row 0 is the last column, where every input is high
+        let combination = rows - 1 - row;
+        let values: Vec<bool> = (0..inputs).map(|i| (combination >> (inputs - 1 - i)) & 1 == 1).collect();
+        result.set(row, f(&values));
+    }
+    result
+}
+
+fn output_signal<B: BitSet>(expr: &Expr, inputs: usize, rows: usize) -> CareSignal<B> {
+    debug_assert_eq!(rows, 1 << inputs);
+    let is = |value: Value| column::<B>(rows, |values| expr.eval(values) == value);
+
+    let (low, high) = (is(Value::Low), is(Value::High));
+    let care = !is(Value::DontCare) & B::ones(rows);
+    let strong = low.clone() | high.clone();
+    CareSignal::new(Signal::new(rows, low, high, strong), care)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first_ok = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    first_ok && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && name != "Z" && name != "X"
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Name(String),
+    Const(Value),
+    /// one of `=!~&^|?:()`
+    Symbol(char),
+    /// a newline, `;` or the end of the string
+    EndLine,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let c = chars[position];
+        let start = position;
+        position += 1;
+
+        let token = match c {
+            '\n' | ';' => Token::EndLine,
+            c if c.is_whitespace() => continue,
+            '0' => Token::Const(Value::Low),
+            '1' => Token::Const(Value::High),
+            '=' | '!' | '~' | '&' | '^' | '|' | '?' | ':' | '(' | ')' => Token::Symbol(c),
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                while position < chars.len() && (chars[position].is_ascii_alphanumeric() || chars[position] == '_') {
+                    position += 1;
+                }
+                match chars[start..position].iter().collect::<String>().as_str() {
+                    "Z" => Token::Const(Value::Z),
+                    "X" => Token::Const(Value::DontCare),
+                    name => Token::Name(name.to_string()),
+                }
+            }
+            character => return Err(ExprError::BadCharacter { character, position: start }),
+        };
+        tokens.push((start, token));
+    }
+
+    tokens.push((chars.len(), Token::EndLine));
+    Ok(tokens)
+}
+
+/// Recursive descent parser, each method parses one precedence level.
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    inputs: &'a [&'a str],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].1
+    }
+
+    fn next(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.index].clone();
+        //the last token is always EndLine, keep returning it
+        self.index = (self.index + 1).min(self.tokens.len() - 1);
+        token
+    }
+
+    fn expect(&mut self, expected: Token, description: &'static str) -> Result<(), ExprError> {
+        let (position, token) = self.next();
+        if token == expected {
+            Ok(())
+        } else {
+            Err(ExprError::Expected { expected: description, position })
+        }
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.index == self.tokens.len() - 1
+    }
+
+    /// Parse a `name = expression` line, skipping empty lines. Returns `None` at the end of the string.
+    fn line(&mut self) -> Result<Option<(String, Expr)>, ExprError> {
+        while *self.peek() == Token::EndLine {
+            if self.at_end() {
+                return Ok(None);
+            }
+            self.next();
+        }
+
+        let name = match self.next() {
+            (_, Token::Name(name)) => name,
+            (position, _) => return Err(ExprError::Expected { expected: "output name", position }),
+        };
+        self.expect(Token::Symbol('='), "'='")?;
+        let expr = self.select()?;
+        self.expect(Token::EndLine, "end of line")?;
+
+        Ok(Some((name, expr)))
+    }
+
+    fn select(&mut self) -> Result<Expr, ExprError> {
+        let cond = self.or()?;
+        if !self.eat('?') {
+            return Ok(cond);
+        }
+
+        let a = self.select()?;
+        self.expect(Token::Symbol(':'), "':'")?;
+        let b = self.select()?;
+        Ok(Expr::Select(Box::new(cond), Box::new(a), Box::new(b)))
+    }
+
+    fn or(&mut self) -> Result<Expr, ExprError> {
+        let mut result = self.xor()?;
+        while self.eat('|') {
+            result = Expr::Or(Box::new(result), Box::new(self.xor()?));
+        }
+        Ok(result)
+    }
+
+    fn xor(&mut self) -> Result<Expr, ExprError> {
+        let mut result = self.and()?;
+        while self.eat('^') {
+            result = Expr::Xor(Box::new(result), Box::new(self.and()?));
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExprError> {
+        let mut result = self.not()?;
+        while self.eat('&') {
+            result = Expr::And(Box::new(result), Box::new(self.not()?));
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<Expr, ExprError> {
+        if self.eat('!') || self.eat('~') {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+
+        match self.next() {
+            (_, Token::Const(value)) => Ok(Expr::Const(value)),
+            (position, Token::Name(name)) => match self.inputs.iter().position(|&i| i == name) {
+                Some(index) => Ok(Expr::Input(index)),
+                None => Err(ExprError::UnknownName { name, position }),
+            },
+            (_, Token::Symbol('(')) => {
+                let expr = self.select()?;
+                self.expect(Token::Symbol(')'), "')'")?;
+                Ok(expr)
+            }
+            (position, _) => Err(ExprError::Expected { expected: "expression", position }),
+        }
+    }
+}
+
+/// Reasons the input names or output expressions passed to `ExprQuery::parse` can be invalid.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExprError {
+    /// a name is not an identifier, is one of the constants `Z` and `X`, or is used more than once
+    BadName { name: String },
+    /// the expression at the given char position uses a name that is not an input
+    UnknownName { name: String, position: usize },
+    /// unexpected character at the given char position
+    BadCharacter { character: char, position: usize },
+    /// something else was found at the given char position
+    Expected { expected: &'static str, position: usize },
+    /// the truth table for this many inputs has more rows than fit in the bitset type
+    TooManyInputs { inputs: usize, max_rows: usize },
+    /// there are no output expressions
+    NoOutputs,
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            ExprError::BadName { name } =>
+                write!(f, "Invalid or duplicate name '{}'", name),
+            ExprError::UnknownName { name, position } =>
+                write!(f, "Unknown input '{}' at position {}", name, position),
+            ExprError::BadCharacter { character, position } =>
+                write!(f, "Unexpected character '{}' at position {}", character, position),
+            ExprError::Expected { expected, position } =>
+                write!(f, "Expected {} at position {}", expected, position),
+            ExprError::TooManyInputs { inputs, max_rows } =>
+                write!(f, "{} inputs too many for bitset type with {} bits", inputs, max_rows),
+            ExprError::NoOutputs =>
+                write!(f, "At least one output expression is required"),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
@@ -1,9 +1,6 @@
 #![allow(dead_code)]
-#![feature(const_fn)]
 
-use std::time::Instant;
-
-use crate::pathfind::main_pathfind;
+use crate::pathfind::{solve, SolveOptions, Strategy};
 use crate::signal::{CareSignal, Query, Signal};
 
 #[cfg(test)]
@@ -12,8 +9,13 @@ mod test;
 mod signal;
 mod vec_set;
 mod bit;
-//mod custom;
+mod bitset;
+mod expr;
+mod export;
+mod custom;
 mod pathfind;
+mod solution;
+mod symmetry;
 
 fn main() {
     //3 input NAND
@@ -87,12 +89,30 @@ fn main() {
     println!("Target output: {:#?}", query.outputs[0]);
     println!("given inputs: {:?}", query.inputs);
 
-    let start = Instant::now();
-
-//    main_custom(&query, 6);
+    let options = SolveOptions { max_devices: 10, strategy: Strategy::Bfs, ..Default::default() };
+    let report = match solve(&query, &options) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Invalid query: {}", e);
+            return;
+        }
+    };
 
-    main_pathfind(&query, 10);
+    match &report.solution {
+        None => println!("No solution found"),
+        Some(solution) => {
+            println!("Found solution, device count: {}", solution.device_count());
+            print!("{}", solution);
+        }
+    }
 
-    let end = Instant::now();
-    println!("Took {}s", (end - start).as_secs_f32());
+    let stats = &report.stats;
+    println!("Expanded per depth: {:?}", stats.expanded_per_depth);
+    println!("Generated per depth: {:?}", stats.generated_per_depth);
+    println!("Branching factor: {:.2}", stats.branching_factor());
+    println!("SUCCESSOR_TIME: {:?}", stats.successor_time);
+    println!("DONE_TIME: {:?}", stats.done_time);
+    println!("ADD_AS_FREE_TIME: {:?}", stats.add_as_free_time);
+    println!("CLONE_TIME: {:?}", stats.clone_time);
+    println!("Took {}s", stats.total_time.as_secs_f32());
 }
\ No newline at end of file
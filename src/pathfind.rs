@@ -1,186 +1,960 @@
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::cmp::{min, Reverse};
+use std::collections::{BinaryHeap, BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 use std::time::{Duration, Instant};
 
-use itertools::Itertools;
-use pathfinding::prelude::bfs;
+use crate::custom;
+use crate::signal::{BitSet, Query, QueryError, Signal};
+use crate::solution::{CostModel, Kind, Solution, SolutionKey, Step};
+use crate::symmetry::Symmetries;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Strategy {
+    /// breadth-first search over positions, remembers every visited position
+    Bfs,
+    /// iterative-deepening depth-first search, memory linear in the number of devices
+    IterativeDeepening,
+    /// uniform-cost search, finds the cheapest circuit according to `SolveOptions::cost`
+    UniformCost,
+    /// A* search using a lower bound on the number of devices still needed for the outputs,
+    /// also finds the cheapest circuit according to `SolveOptions::cost`
+    AStar,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SolveOptions {
+    /// the maximum number of devices in a solution, the search gives up after this depth. A transmission gate is
+    /// placed as one device but counts as two, like its transistors.
+    pub max_devices: usize,
+    pub strategy: Strategy,
+    /// Only generate independent devices in one canonical order and treat positions that are the same up to
+    /// swapping interchangeable inputs as equal. Not used by `Strategy::IterativeDeepening`.
+    pub symmetry_breaking: bool,
+    /// The number of threads used to expand each level of `Strategy::Bfs`, defaults to the number of cores.
+    /// The solution found does not depend on this.
+    pub threads: usize,
+    /// The maximum number of distinct solutions with the minimum number of devices to collect, solutions that
+    /// are the same up to renaming nets are only returned once. `Strategy::UniformCost` and `Strategy::AStar` only
+    /// return a single solution.
+    pub max_solutions: usize,
+    /// The cost to minimize, only used by `Strategy::UniformCost` and `Strategy::AStar`. The other strategies
+    /// find the circuit with the fewest devices.
+    pub cost: CostModel,
+    /// The maximum number of devices in series between a power signal and any net, devices connected to a gate
+    /// don't count.
+    pub max_series: Option<usize>,
+    /// Also place transmission gates, for every gate signal whose complement is available as a gate as well.
+    pub transmission_gates: bool,
+    /// Also place resistors from every power signal. Like with `pull_devices`, a strong signal can then be
+    /// connected to a weak signal of the opposite level, see `Solution::static_current`.
+    pub resistors: bool,
+    /// Also place always-on weak pull-ups and pull-downs from power signals that are high or low in every row.
+    pub pull_devices: bool,
+    /// Connect outputs with `Signal::resolve`, so two signals pulling to different levels with the same strength
+    /// give `X` instead of being skipped. `X` in an output string is a don't care row, so `X` only matches an
+    /// output built from a `Signal` with `X`, but it can end up in rows that are don't care, see
+    /// `Solution::contention`. Implies the strong over weak resolution of `resistors`.
+    pub allow_contention: bool,
+    /// Also use signals that are weak or floating in some rows as gates. A weak level switches a transistor like a
+    /// strong one but only gives a weak output, and a floating gate gives `X` in the rows where the power signal
+    /// is driven, which only fits don't care rows of the outputs. This only concerns the signals built during the
+    /// search, the query inputs must still be strong or `Query::check` rejects them with `NonStrongInput`.
+    pub weak_gates: bool,
+}
+
+impl SolveOptions {
+    pub(crate) fn gateless_kinds(&self) -> Vec<Kind> {
+        let mut kinds = Vec::new();
+        if self.resistors {
+            kinds.push(Kind::Resistor);
+        }
+        if self.pull_devices {
+            kinds.extend_from_slice(&[Kind::PullUp, Kind::PullDown]);
+        }
+        kinds
+    }
+
+    /// Whether a strong level can override a weak level of the other level.
+    pub(crate) fn is_ratioed(&self) -> bool {
+        self.resistors || self.pull_devices || self.allow_contention
+    }
+
+    /// The function used to merge the output of a new device into an existing signal.
+    pub(crate) fn connect<B: BitSet>(&self) -> fn(&Signal<B>, &Signal<B>) -> Option<Signal<B>> {
+        if self.allow_contention {
+            Signal::connect_contention
+        } else if self.is_ratioed() {
+            Signal::connect_ratioed
+        } else {
+            Signal::connect
+        }
+    }
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            max_devices: 8,
+            strategy: Strategy::Bfs,
+            symmetry_breaking: true,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            max_solutions: 1,
+            cost: CostModel::default(),
+            max_series: None,
+            transmission_gates: false,
+            resistors: false,
+            pull_devices: false,
+            allow_contention: false,
+            weak_gates: false,
+        }
+    }
+}
+
+/// Statistics collected during a single call to `solve`. When multiple threads are used the times are summed
+/// over all threads.
+#[derive(Debug, Default, Clone)]
+pub struct SolveStats {
+    /// number of positions expanded at each depth, where depth is the number of devices placed,
+    /// for iterative deepening this is summed over all iterations
+    pub expanded_per_depth: Vec<usize>,
+    /// number of new positions generated at each depth
+    pub generated_per_depth: Vec<usize>,
+
+    pub total_time: Duration,
+    pub successor_time: Duration,
+    pub done_time: Duration,
+    pub add_as_free_time: Duration,
+    pub clone_time: Duration,
+    pub canonicalize_time: Duration,
+}
+
+impl SolveStats {
+    pub fn expanded(&self) -> usize {
+        self.expanded_per_depth.iter().sum()
+    }
+
+    pub fn generated(&self) -> usize {
+        self.generated_per_depth.iter().sum()
+    }
+
+    /// The average number of new positions generated per expanded position.
+    pub fn branching_factor(&self) -> f64 {
+        if self.expanded() == 0 {
+            0.0
+        } else {
+            self.generated() as f64 / self.expanded() as f64
+        }
+    }
+
+    pub(crate) fn count(list: &mut Vec<usize>, depth: usize, amount: usize) {
+        if list.len() <= depth {
+            list.resize(depth + 1, 0);
+        }
+        list[depth] += amount;
+    }
+
+    /// Add the statistics collected by another thread.
+    fn add(&mut self, other: &SolveStats) {
+        for (depth, &amount) in other.expanded_per_depth.iter().enumerate() {
+            SolveStats::count(&mut self.expanded_per_depth, depth, amount);
+        }
+        for (depth, &amount) in other.generated_per_depth.iter().enumerate() {
+            SolveStats::count(&mut self.generated_per_depth, depth, amount);
+        }
+
+        self.successor_time += other.successor_time;
+        self.done_time += other.done_time;
+        self.add_as_free_time += other.add_as_free_time;
+        self.clone_time += other.clone_time;
+        self.canonicalize_time += other.canonicalize_time;
+    }
+}
+
+#[derive(Debug)]
+pub struct SolveReport<B: BitSet> {
+    /// the first solution that was found
+    pub solution: Option<Solution<B>>,
+    /// all distinct solutions that were found, at most `SolveOptions::max_solutions`
+    pub solutions: Vec<Solution<B>>,
+    pub stats: SolveStats,
+}
+
+/// Collects the distinct solutions found by a search.
+pub(crate) struct Solutions<'a, 'q, B: BitSet> {
+    query: &'a Query<'q, B>,
+    /// always detected, so solutions that only differ by swapping interchangeable inputs count as the same
+    symmetries: Symmetries,
+    max: usize,
+    keys: HashSet<SolutionKey<B>>,
+    found: Vec<Solution<B>>,
+}
+
+impl<'a, 'q, B: BitSet> Solutions<'a, 'q, B> {
+    fn new(query: &'a Query<'q, B>, options: &SolveOptions) -> Self {
+        Solutions {
+            query,
+            symmetries: Symmetries::detect(query),
+            max: options.max_solutions.max(1),
+            keys: HashSet::new(),
+            found: Vec::new(),
+        }
+    }
+
+    /// Add the solution built by `steps` if it is new, returns true if no more solutions are wanted.
+    pub fn add(&mut self, steps: &[Step<B>]) -> bool {
+        let solution = Solution::from_steps(self.query, steps);
+        let symmetries = &self.symmetries;
+        let key = (0..symmetries.len()).map(|i| solution.key(symmetries.perm(i))).min().unwrap();
+
+        if self.keys.insert(key) {
+            self.found.push(solution);
+        }
+        self.is_full()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.found.len() >= self.max
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.found.is_empty()
+    }
+}
+
+/// Everything about the query that stays the same during a search.
+struct Context<'a, 'q, B: BitSet> {
+    query: &'a Query<'q, B>,
+    symmetries: Symmetries,
+    canonical_order: bool,
+    /// remember every way a position was reached instead of only the first one, to find all solutions
+    all_paths: bool,
+    max_series: Option<usize>,
+    transmission_gates: bool,
+    /// the kinds without a gate that can be placed
+    gateless: Vec<Kind>,
+    /// whether a strong level can override a weak one when merging
+    ratioed: bool,
+    weak_gates: bool,
+    connect: fn(&Signal<B>, &Signal<B>) -> Option<Signal<B>>,
+}
+
+impl<'a, 'q, B: BitSet> Context<'a, 'q, B> {
+    fn new(query: &'a Query<'q, B>, options: &SolveOptions) -> Self {
+        let symmetries = if options.symmetry_breaking { Symmetries::detect(query) } else { Symmetries::none() };
+
+        Context {
+            query,
+            symmetries,
+            canonical_order: options.symmetry_breaking,
+            all_paths: options.max_solutions > 1,
+            max_series: options.max_series,
+            transmission_gates: options.transmission_gates,
+            gateless: options.gateless_kinds(),
+            ratioed: options.is_ratioed(),
+            weak_gates: options.weak_gates,
+            connect: options.connect(),
+        }
+    }
+}
+
+type OrderKey<B> = (Kind, Signal<B>, Option<Signal<B>>, Option<Signal<B>>);
+
+/// Decides which steps are allowed to follow the steps a position was reached with: steps that don't influence
+/// each other give the same position in either order, so only the order with non-decreasing keys is generated.
+/// Signals in the key are replaced by their symmetry class, so the order stays the same when a position is
+/// replaced by an equivalent one.
+struct Order<'p, B: BitSet> {
+    lasts: &'p [Step<B>],
+    min_key: OrderKey<B>,
+    /// the class of each signal in the position, empty if there are no symmetries
+    classes: Vec<(Signal<B>, Signal<B>)>,
+}
+
+impl<'p, B: BitSet> Order<'p, B> {
+    fn new(ctx: &Context<B>, pos: &'p Pos<B>) -> Option<Self> {
+        if pos.lasts.is_empty() { return None; }
+
+        let classes = if ctx.symmetries.len() == 1 {
+            vec![]
+        } else {
+            pos.power_cands.iter().chain(&pos.gate_cands).chain(pos.built_signals.keys())
+                .map(|s| (s.clone(), ctx.symmetries.class(s)))
+                .collect()
+        };
+
+        let class = |s| ctx.symmetries.class(s);
+        let min_key = pos.lasts.iter()
+            .map(|l| (l.kind, class(&l.power), l.gate.as_ref().map(class), l.merge.as_ref().map(class)))
+            .min().unwrap();
 
-use crate::signal::{BitSet, Query, Signal};
-use std::mem::swap;
+        Some(Order { lasts: &pos.lasts, min_key, classes })
+    }
+
+    fn key(&self, step: &Step<B>) -> OrderKey<B> {
+        let class = |s: &Signal<B>| {
+            if self.classes.is_empty() { return s.clone(); }
+            self.classes.iter().find(|(k, _)| k == s).unwrap().1.clone()
+        };
+        (step.kind, class(&step.power), step.gate.as_ref().map(class), step.merge.as_ref().map(class))
+    }
+
+    fn allows(&self, next: &Step<B>) -> bool {
+        self.min_key <= self.key(next) || self.lasts.iter().any(|last| dependent(last, next))
+    }
+}
 
-static mut SUCCESSOR_TIME: Duration = Duration::from_secs(0);
-static mut DONE_TIME: Duration = Duration::from_secs(0);
-static mut ADD_AS_FREE_TIME: Duration = Duration::from_secs(0);
-static mut CLONE_FOR_NEXT_TIME: Duration = Duration::from_secs(0);
-static mut CLONE_TIME: Duration = Duration::from_secs(0);
+/// Whether swapping `last` and `next` could give a different result.
+fn dependent<B: BitSet>(last: &Step<B>, next: &Step<B>) -> bool {
+    let uses = |step: &Step<B>, signal: &Signal<B>| {
+        step.power == *signal || step.gate.as_ref() == Some(signal) || step.merge.as_ref() == Some(signal) ||
+            step.inverted_gate().as_ref() == Some(signal)
+    };
+    let merged = |step: &Step<B>, other: &Step<B>| {
+        step.merge.as_ref().is_some_and(|m| uses(other, m))
+    };
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
-enum Kind {
-    PMOS,
-    NMOS,
+    uses(next, &last.output) || last.output == next.output ||
+        uses(last, &next.output) || merged(last, next) || merged(next, last)
 }
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
-struct Device<B: BitSet> {
-    kind: Kind,
-    gate: Signal<B>,
-    power: Signal<B>,
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+struct Built {
+    /// not used as power or gate yet, so new devices can still be merged into it
+    free: bool,
+    /// the number of devices in series between a power signal and this signal,
+    /// only tracked if there is a `SolveOptions::max_series`
+    series: usize,
 }
 
-#[derive(Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Debug)]
 struct Pos<B: BitSet> {
     gates_left: usize,
 
     power_cands: Vec<Signal<B>>,
     gate_cands: Vec<Signal<B>>,
 
-    built_signals: BTreeMap<Signal<B>, bool>,
+    built_signals: BTreeMap<Signal<B>, Built>,
+    /// The steps this position was reached with, only kept when generating steps in canonical order.
+    /// These are not part of the identity of the position: when the same position is reached multiple times
+    /// the steps are combined instead, so no position is expanded more than necessary.
+    lasts: Vec<Step<B>>,
+}
+
+impl<B: BitSet> PartialEq for Pos<B> {
+    fn eq(&self, other: &Self) -> bool {
+        self.gates_left == other.gates_left &&
+            self.power_cands == other.power_cands &&
+            self.gate_cands == other.gate_cands &&
+            self.built_signals == other.built_signals
+    }
+}
+
+impl<B: BitSet> Eq for Pos<B> {}
+
+impl<B: BitSet> Hash for Pos<B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.gates_left.hash(state);
+        self.power_cands.hash(state);
+        self.gate_cands.hash(state);
+        self.built_signals.hash(state);
+    }
+}
+
+/// A possible next position, `perm` is the symmetry that was applied to the position after taking `step`.
+struct Successor<B: BitSet> {
+    step: Step<B>,
+    perm: usize,
+    pos: Pos<B>,
 }
 
-impl<B: BitSet> Clone for Pos<B> {
-    fn clone(&self) -> Self {
+impl<B: BitSet> Pos<B> {
+    fn start(query: &Query<B>, max_devices: usize) -> Pos<B> {
+        Pos {
+            gates_left: max_devices,
+
+            power_cands: query.power.to_vec(),
+            gate_cands: query.inputs.to_vec(),
+            built_signals: Default::default(),
+            lasts: vec![],
+        }
+    }
+
+    /// Add the steps in `lasts` that weren't known yet, and return those.
+    fn merge_lasts(&mut self, lasts: &[Step<B>]) -> Vec<Step<B>> {
+        let new: Vec<Step<B>> = lasts.iter().filter(|l| !self.lasts.contains(l)).cloned().collect();
+        self.lasts.extend_from_slice(&new);
+        new
+    }
+
+    /// Whether all outputs are built.
+    fn is_done(&self, ctx: &Context<B>, stats: &mut SolveStats) -> bool {
+        let start = Instant::now();
+        let result = ctx.query.outputs.iter().all(|cs|
+            if cs.is_exact() {
+                self.built_signals.contains_key(&cs.signal)
+            } else {
+                self.built_signals.keys().any(|p| cs.matches(p))
+            }
+        );
+        stats.done_time += start.elapsed();
+        result
+    }
+
+    /// Admissible estimate of the number of devices still needed: each output has to end up on a built signal,
+    /// either by merging new devices into one of the free signals or by building it from scratch.
+    fn lower_bound(&self, ctx: &Context<B>) -> usize {
+        let floating = Signal::floating(ctx.query.rows());
+
+        ctx.query.outputs.iter().map(|cs| {
+            if self.built_signals.keys().any(|s| cs.matches(s)) {
+                return 0;
+            }
+
+            self.built_signals.iter()
+                .filter(|&(_, built)| built.free)
+                .map(|(s, _)| s)
+                .chain(std::iter::once(&floating))
+                .filter_map(|base| cs.devices_needed(base, ctx.ratioed))
+                .min()
+                .unwrap_or(usize::MAX)
+        }).max().unwrap_or(0)
+    }
+
+    /// Replace this position by the smallest equivalent position under the symmetries of the query,
+    /// returns the index of the permutation that was applied.
+    fn canonicalize(self, ctx: &Context<B>, stats: &mut SolveStats) -> (usize, Pos<B>) {
+        if ctx.symmetries.len() == 1 {
+            return (0, self);
+        }
+
+        let start = Instant::now();
+        let mut best = (0, self.built_signals.iter().map(|(s, &b)| (s.clone(), b)).collect::<Vec<_>>());
+        for index in 1..ctx.symmetries.len() {
+            let perm = ctx.symmetries.perm(index);
+            let mut built: Vec<(Signal<B>, Built)> = self.built_signals.iter()
+                .map(|(s, &b)| (s.permute_rows(perm), b))
+                .collect();
+            built.sort_unstable();
+
+            if built < best.1 {
+                best = (index, built);
+            }
+        }
+        stats.canonicalize_time += start.elapsed();
+
+        match best {
+            (0, _) => (0, self),
+            (index, built) => {
+                let perm = ctx.symmetries.perm(index);
+                let lasts = self.lasts.iter().map(|step| step.permute_rows(perm)).collect();
+                (index, Pos { built_signals: built.into_iter().collect(), lasts, ..self })
+            }
+        }
+    }
+
+    /// Clone as the start of a next position, so without `lasts`.
+    fn timed_clone(&self, stats: &mut SolveStats) -> Pos<B> {
         let start = Instant::now();
         let result = Pos {
             gates_left: self.gates_left,
             power_cands: self.power_cands.clone(),
             gate_cands: self.gate_cands.clone(),
             built_signals: self.built_signals.clone(),
+            lasts: vec![],
         };
-
-        let end = Instant::now();
-        unsafe { CLONE_TIME += end - start }
+        stats.clone_time += start.elapsed();
         result
     }
-}
-
-impl<B: BitSet> Pos<B> {
-    fn clone_for_next(&self) -> Pos<B> {
-        let start = Instant::now();
-
-        let mut result = self.clone();
-        result.gates_left -= 1;
-
-        let end = Instant::now();
-        unsafe { CLONE_FOR_NEXT_TIME += end - start; }
 
+    fn clone_for_next(&self, kind: Kind, stats: &mut SolveStats) -> Pos<B> {
+        let mut result = self.timed_clone(stats);
+        result.gates_left -= kind.size();
         result
     }
 
-    fn successors(&self) -> Vec<Pos<B>> {
+    /// All possible next positions, together with the step that leads to each of them.
+    fn steps(&self, ctx: &Context<B>, stats: &mut SolveStats) -> Vec<Successor<B>> {
         let start = Instant::now();
         let mut result = Vec::new();
 
         if self.gates_left == 0 { return result; };
 
-        for &power in self.power_cands.iter().chain(self.built_signals.keys()) {
-            for &gate in self.gate_cands.iter().chain(self.built_signals.keys()) {
-                let mut next = self.clone();
+        let order = Order::new(ctx, self);
+
+        for power in self.power_cands.iter().chain(self.built_signals.keys()) {
+            for gate in self.gate_cands.iter().chain(self.built_signals.keys()) {
+                let mut next = self.timed_clone(stats);
+
+                for used in [power, gate] {
+                    if let Some(built) = next.built_signals.get_mut(used) {
+                        built.free = false;
+                    }
+                }
+
+                next.add_device(ctx, &order, Kind::PMOS, Some(gate), power, &mut result, stats);
+                next.add_device(ctx, &order, Kind::NMOS, Some(gate), power, &mut result, stats);
 
-                next.built_signals.entry(power).and_modify(|v| *v = false);
-                next.built_signals.entry(gate).and_modify(|v| *v = false);
+                if ctx.transmission_gates {
+                    let inverted = gate.complement();
+                    if self.gate_cands.contains(&inverted) || self.built_signals.contains_key(&inverted) {
+                        if let Some(built) = next.built_signals.get_mut(&inverted) {
+                            built.free = false;
+                        }
+                        next.add_device(ctx, &order, Kind::TransmissionGate, Some(gate), power, &mut result, stats);
+                    }
+                }
+            }
 
-                next.add_device(Signal::pmos(gate, power), &mut result);
-                next.add_device(Signal::nmos(gate, power), &mut result);
+            for &kind in &ctx.gateless {
+                let mut next = self.timed_clone(stats);
+                if let Some(built) = next.built_signals.get_mut(power) {
+                    built.free = false;
+                }
+                next.add_device(ctx, &order, kind, None, power, &mut result, stats);
             }
         }
-        let end = Instant::now();
-        unsafe { SUCCESSOR_TIME += end - start; }
+        stats.successor_time += start.elapsed();
 
         result
     }
 
-    fn add_device(&self, output: Option<Signal<B>>, result: &mut Vec<Pos<B>>) {
-        if let Some(output) = output {
+    #[allow(clippy::too_many_arguments)]
+    fn add_device(
+        &self, ctx: &Context<B>, order: &Option<Order<B>>, kind: Kind, gate: Option<&Signal<B>>, power: &Signal<B>,
+        result: &mut Vec<Successor<B>>, stats: &mut SolveStats,
+    ) {
+        let allowed = |step: &Step<B>| order.as_ref().is_none_or(|order| order.allows(step));
+        if kind.size() > self.gates_left { return; }
+
+        let series = match ctx.max_series {
+            None => 0,
+            Some(max_series) => {
+                let series = self.built_signals.get(power).map_or(0, |b| b.series) + 1;
+                if series > max_series { return; }
+                series
+            }
+        };
+
+        if let Some(output) = kind.apply(gate, power, ctx.weak_gates) {
             //add as free
-            self.add_as_free(output, result);
+            let step = Step { kind, power: power.clone(), gate: gate.cloned(), merge: None, output: output.clone() };
+            if allowed(&step) {
+                self.add_as_free(ctx, step, series, result, stats);
+            }
 
             //merge with other frees
-            for (&other, &free) in &self.built_signals {
-                if free {
-                    if let Some(combined) = Signal::connect(output, other) {
-                        let mut next = self.clone();
-
-                        assert!(next.built_signals.remove(&other).is_some());
-                        next.add_as_free(combined, result);
+            for (other, &built) in &self.built_signals {
+                if built.free {
+                    if let Some(combined) = (ctx.connect)(&output, other) {
+                        let step = Step {
+                            kind, power: power.clone(), gate: gate.cloned(), merge: Some(other.clone()), output: combined,
+                        };
+                        if !allowed(&step) { continue; }
+
+                        let mut next = self.timed_clone(stats);
+                        assert!(next.built_signals.remove(other).is_some());
+                        next.add_as_free(ctx, step, series.max(built.series), result, stats);
                     }
                 }
             }
         }
     }
 
-    fn add_as_free(&self, new: Signal<B>, result: &mut Vec<Pos<B>>) {
+    fn add_as_free(
+        &self, ctx: &Context<B>, step: Step<B>, series: usize, result: &mut Vec<Successor<B>>, stats: &mut SolveStats,
+    ) {
         let start = Instant::now();
 
-        if self.built_signals.get(&new) != Some(&true) {
-            let mut next = self.clone_for_next();
-            next.built_signals.insert(new, true);
-            result.push(next);
+        if self.built_signals.get(&step.output).is_none_or(|b| !b.free) {
+            let mut next = self.clone_for_next(step.kind, stats);
+            next.built_signals.insert(step.output.clone(), Built { free: true, series });
+            if ctx.canonical_order {
+                next.lasts.push(step.clone());
+            }
+
+            let (perm, pos) = next.canonicalize(ctx, stats);
+            result.push(Successor { step, perm, pos });
         }
 
-        let end = Instant::now();
-        unsafe { ADD_AS_FREE_TIME += end - start; }
+        stats.add_as_free_time += start.elapsed();
+    }
+}
+
+/// A position in the search tree, only the step leading to it is kept to reconstruct the solution.
+struct Node<B: BitSet> {
+    parent: usize,
+    step: Option<Step<B>>,
+    perm: usize,
+    /// other ways to reach this position as `(parent, step, perm)`, only kept if `Context::all_paths`
+    alternatives: Vec<(usize, Step<B>, usize)>,
+}
+
+impl<B: BitSet> Node<B> {
+    fn root() -> Self {
+        Node { parent: 0, step: None, perm: 0, alternatives: vec![] }
     }
 }
 
-pub fn main_pathfind<B: BitSet>(query: &Query<B>, max_gates: usize) -> Option<usize> {
-    query.check();
+/// Collect the steps leading to `index`.
+fn collect_steps<B: BitSet>(ctx: &Context<B>, nodes: &[Node<B>], mut index: usize) -> Vec<Step<B>> {
+    let mut path = Vec::new();
+    while let Some(step) = &nodes[index].step {
+        path.push((step, nodes[index].perm));
+        index = nodes[index].parent;
+    }
+    path.reverse();
+
+    unpermute_path(ctx, &path)
+}
+
+/// Call `f` with the steps of every path leading to `index` until it returns true, returns whether it did.
+/// `suffix` contains the path from `index` to the final position in reverse.
+fn for_each_path<'n, B: BitSet>(
+    ctx: &Context<B>, nodes: &'n [Node<B>], index: usize, suffix: &mut Vec<(&'n Step<B>, usize)>,
+    f: &mut dyn FnMut(&[Step<B>]) -> bool,
+) -> bool {
+    let node = &nodes[index];
+    let step = match &node.step {
+        None => {
+            let path: Vec<(&Step<B>, usize)> = suffix.iter().rev().copied().collect();
+            return f(&unpermute_path(ctx, &path));
+        }
+        Some(step) => step,
+    };
 
-    //to use for done check, if there are no outputs the mask doesn't matter
-    let ignore_mask = query.outputs
-        .first().map_or(B::zero(), |cs| cs.signal.ignored_mask());
+    let edges = std::iter::once((node.parent, step, node.perm))
+        .chain(node.alternatives.iter().map(|(parent, step, perm)| (*parent, step, *perm)));
+    for (parent, step, perm) in edges {
+        suffix.push((step, perm));
+        let stop = for_each_path(ctx, nodes, parent, suffix, f);
+        suffix.pop();
 
-    /*let pos: Pos<u8> = Pos {
-        power_signals: vec![Signal::from_str("1Z1Z"), Signal::from_str("1111"), Signal::from_str("0000")].iter().copied().collect(),
-        gate_signals: vec![Signal::from_str("1Z1Z"), Signal::from_str("0101"), Signal::from_str("0011")].iter().copied().collect(),
-        free_signals: vec![Signal::from_str("1Z1Z")].iter().copied().collect(),
-        gates_left: 5,
-    };*/
+        if stop { return true; }
+    }
+    false
+}
+
+/// Each step in `path` is relative to the canonicalized position before it, so the symmetries applied along
+/// the way are undone to get steps relative to the actual query.
+fn unpermute_path<B: BitSet>(ctx: &Context<B>, path: &[(&Step<B>, usize)]) -> Vec<Step<B>> {
+    let symmetries = &ctx.symmetries;
+    let mut inverse = 0;
+    path.iter().map(|&(step, perm)| {
+        let actual = step.permute_rows(symmetries.perm(inverse));
+        inverse = symmetries.then(symmetries.inverse(perm), inverse);
+        actual
+    }).collect()
+}
 
-//    println!("Parent: {:?}", pos);
-//    println!("Successors:\n{}", pos.successors(0b1111).iter().map(|p| format!("{:?}", p)).join("\n"));
-//    return;
+/// Search for a circuit implementing `query` with the least amount of devices.
+/// Nothing is printed, everything of interest is returned in the `SolveReport`.
+pub fn solve<B: BitSet>(query: &Query<B>, options: &SolveOptions) -> Result<SolveReport<B>, QueryError> {
+    query.check()?;
 
-    let start = Pos {
-        gates_left: max_gates,
+    let start_time = Instant::now();
+    let mut stats = SolveStats::default();
+    let mut solutions = Solutions::new(query, options);
 
-        power_cands: query.power.iter().copied().collect(),
-        gate_cands: query.inputs.iter().copied().collect(),
-        built_signals: Default::default(),
+    match options.strategy {
+        Strategy::Bfs => find_solution_bfs(&Context::new(query, options), options, &mut solutions, &mut stats),
+        Strategy::IterativeDeepening => custom::find_solution(query, options, &mut solutions, &mut stats),
+        Strategy::UniformCost | Strategy::AStar =>
+            find_solution_best_first(&Context::new(query, options), options, &mut solutions, &mut stats),
     };
 
-    let done = |p: &Pos<B>| -> bool {
-        let start = Instant::now();
-        let result = query.outputs.iter().all(|cs|
-            if cs.care == !ignore_mask {
-                p.built_signals.get(&cs.signal).is_some()
-            } else {
-                p.built_signals.keys().any(|&p| cs.matches(p))
+    let solutions = solutions.found;
+    stats.total_time = start_time.elapsed();
+
+    Ok(SolveReport { solution: solutions.first().cloned(), solutions, stats })
+}
+
+fn find_solution_bfs<B: BitSet>(
+    ctx: &Context<B>, options: &SolveOptions, solutions: &mut Solutions<B>, stats: &mut SolveStats,
+) {
+    let start = Pos::start(ctx.query, options.max_devices);
+
+    let mut nodes = vec![Node::root()];
+    if start.is_done(ctx, stats) {
+        solutions.add(&[]);
+        return;
+    }
+
+    let mut frontier = vec![(0, start)];
+    //positions reached with a transmission gate skip a level, they wait here together with whether they are done
+    let mut pending = Vec::new();
+
+    //level-synchronous bfs on the size of the devices, so we can keep statistics per depth and expand each level
+    //in parallel
+    let mut depth = 0;
+    while !frontier.is_empty() || !pending.is_empty() {
+        SolveStats::count(&mut stats.expanded_per_depth, depth, frontier.len());
+
+        let level = expand_level(ctx, &frontier, options.threads.max(1), stats);
+        SolveStats::count(&mut stats.generated_per_depth, depth, level.len());
+
+        let mut next_frontier = Vec::with_capacity(level.len());
+        let mut done = Vec::new();
+        let mut skipped = Vec::new();
+        for (mut next, claim) in level {
+            let alternatives = claim.alternatives.into_iter()
+                .map(|(order, step, perm)| (frontier[order.0].0, step, perm))
+                .collect();
+            let skips = claim.step.kind.size() > 1;
+            nodes.push(Node { parent: frontier[claim.order.0].0, step: Some(claim.step), perm: claim.perm, alternatives });
+            next.lasts = claim.lasts;
+
+            if skips {
+                skipped.push((nodes.len() - 1, next, claim.done));
+                continue;
             }
-        );
-        let end = Instant::now();
-        unsafe { DONE_TIME += end - start; }
-        result
+            if claim.done {
+                done.push(nodes.len() - 1);
+                if !ctx.all_paths { break; }
+            }
+            next_frontier.push((nodes.len() - 1, next));
+        }
+
+        //a position can end up in the frontier twice this way, the visited set of the next level merges their
+        //successors again
+        for (index, next, is_done) in std::mem::replace(&mut pending, skipped) {
+            if is_done {
+                done.push(index);
+            }
+            next_frontier.push((index, next));
+        }
+
+        if !done.is_empty() {
+            for index in done {
+                if for_each_path(ctx, &nodes, index, &mut vec![], &mut |steps| solutions.add(steps)) { break; }
+            }
+            return;
+        }
+
+        frontier = next_frontier;
+        depth += 1;
+    }
+}
+
+/// Number of frontier positions a thread takes at once.
+const CHUNK_SIZE: usize = 64;
+
+/// Expand all positions in `frontier` using `threads` threads. Returns the distinct next positions in the order
+/// a single thread would have found them, so the result doesn't depend on the number of threads.
+fn expand_level<B: BitSet>(
+    ctx: &Context<B>, frontier: &[(usize, Pos<B>)], threads: usize, stats: &mut SolveStats,
+) -> Vec<(Pos<B>, Claim<B>)> {
+    let visited = Visited::new(threads);
+    let next_chunk = AtomicUsize::new(0);
+    //the first frontier position that leads to a solution, the positions after it don't need to be expanded
+    let first_done = AtomicUsize::new(usize::MAX);
+
+    let work = || {
+        let mut stats = SolveStats::default();
+        loop {
+            let begin = next_chunk.fetch_add(CHUNK_SIZE, Ordering::Relaxed);
+            if begin >= frontier.len() { break; }
+
+            let end = min(begin + CHUNK_SIZE, frontier.len());
+            for (index, (_, pos)) in frontier.iter().enumerate().take(end).skip(begin) {
+                if index > first_done.load(Ordering::Relaxed) { break; }
+
+                for (i, successor) in pos.steps(ctx, &mut stats).into_iter().enumerate() {
+                    //all paths to the solutions are needed, so the whole level has to be expanded, and a solution
+                    //that ends with a transmission gate is only found on the level after this one
+                    let skips = successor.step.kind.size() > 1;
+                    if visited.insert(ctx, (index, i), successor, &mut stats) && !skips && !ctx.all_paths {
+                        first_done.fetch_min(index, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        stats
     };
 
-    //TODO try to write our own bfs
-    let result = bfs(&start, Pos::successors, done);
+    if threads == 1 {
+        stats.add(&work());
+    } else {
+        let all_stats: Vec<SolveStats> = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads).map(|_| s.spawn(work)).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        for thread_stats in &all_stats {
+            stats.add(thread_stats);
+        }
+    }
+
+    visited.into_sorted()
+}
 
-    let length = match &result {
-        None => {
-            println!("No solution found");
-            None
+/// How a position in the next frontier was reached. When it is reached multiple times the step that comes first
+/// in `order` is kept and the lasts of all of them are combined.
+struct Claim<B: BitSet> {
+    /// the index of the parent in the frontier and the index of the step in the successors of the parent
+    order: (usize, usize),
+    step: Step<B>,
+    perm: usize,
+    lasts: Vec<Step<B>>,
+    done: bool,
+    /// the other ways this position was reached as `(order, step, perm)`, only kept if `Context::all_paths`
+    alternatives: Vec<((usize, usize), Step<B>, usize)>,
+}
+
+/// The positions in the next frontier, shared between the threads. Split into shards that are locked separately
+/// so the threads don't have to wait on each other all the time.
+struct Visited<B: BitSet> {
+    hasher: RandomState,
+    shards: Vec<Mutex<HashMap<Pos<B>, Claim<B>>>>,
+}
+
+impl<B: BitSet> Visited<B> {
+    fn new(threads: usize) -> Self {
+        let shards = if threads == 1 { 1 } else { threads * 16 };
+        Visited {
+            hasher: RandomState::new(),
+            shards: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
         }
-        Some(solution) => {
-            println!("Found solution, device count: {}", solution.len() - 1);
-            println!("{}", solution.iter().map(|p| format!("{:?}", p)).join("\n"));
-            Some(solution.len() - 1)
+    }
+
+    /// Returns true if the position is new and all outputs are built in it.
+    fn insert(&self, ctx: &Context<B>, order: (usize, usize), successor: Successor<B>, stats: &mut SolveStats) -> bool {
+        let Successor { step, perm, pos: mut next } = successor;
+        let lasts = std::mem::take(&mut next.lasts);
+
+        let shard = self.hasher.hash_one(&next) as usize % self.shards.len();
+        let mut shard = self.shards[shard].lock().unwrap();
+
+        match shard.get_mut(&next) {
+            Some(claim) => {
+                for last in lasts {
+                    if !claim.lasts.contains(&last) {
+                        claim.lasts.push(last);
+                    }
+                }
+                let mut other = (order, step, perm);
+                if other.0 < claim.order {
+                    std::mem::swap(&mut claim.order, &mut other.0);
+                    std::mem::swap(&mut claim.step, &mut other.1);
+                    std::mem::swap(&mut claim.perm, &mut other.2);
+                }
+                if ctx.all_paths {
+                    claim.alternatives.push(other);
+                }
+                false
+            }
+            None => {
+                let done = next.is_done(ctx, stats);
+                shard.insert(next, Claim { order, step, perm, lasts, done, alternatives: vec![] });
+                done
+            }
+        }
+    }
+
+    fn into_sorted(self) -> Vec<(Pos<B>, Claim<B>)> {
+        let mut result: Vec<(Pos<B>, Claim<B>)> = self.shards.into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap())
+            .collect();
+        result.sort_unstable_by_key(|(_, claim)| claim.order);
+
+        //the order the lasts and alternatives were combined in depends on the threads
+        for (_, claim) in &mut result {
+            claim.lasts.sort_unstable();
+            claim.alternatives.sort_unstable();
         }
+        result
+    }
+}
+
+/// Best-first search on the cost of the devices placed so far. `Strategy::AStar` adds a lower bound on the cost
+/// still needed for the outputs to that, `Strategy::UniformCost` doesn't.
+fn find_solution_best_first<B: BitSet>(
+    ctx: &Context<B>, options: &SolveOptions, solutions: &mut Solutions<B>, stats: &mut SolveStats,
+) {
+    let cost = &options.cost;
+    let estimate = |bound: usize| match options.strategy {
+        Strategy::AStar => bound as u32 * cost.min_device(),
+        _ => 0,
     };
 
-    println!("SUCCESSORS_TIME: {:?}", unsafe { SUCCESSOR_TIME });
-    println!("DONE_TIME: {:?}", unsafe { DONE_TIME });
-    println!("ADD_AS_FREE_TIME: {:?}", unsafe { ADD_AS_FREE_TIME });
-    println!("CLONE_FOR_NEXT_TIME: {:?}", unsafe { CLONE_FOR_NEXT_TIME });
-    println!("CLONE_TIME: {:?}", unsafe { CLONE_TIME });
+    let start = Pos::start(ctx.query, options.max_devices);
+    let mut nodes = vec![Node::root()];
+
+    //ordered by lowest estimated total, then by most devices placed to get to a solution faster
+    let mut queue = BinaryHeap::new();
+    queue.push((Reverse(estimate(start.lower_bound(ctx))), 0, 0));
+    let mut positions = vec![Some((0, start.clone()))];
+
+    //for each position the node it was last queued as and its cost, and the steps it was reached with
+    let mut visited: HashMap<Pos<B>, (usize, u32)> = HashMap::new();
+    visited.insert(start, (0, 0));
+
+    while let Some((_, depth, index)) = queue.pop() {
+        //skip positions that were reached again with a lower cost before being expanded
+        let (so_far, pos) = match positions[index].take() {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        //the goal check happens when popping to guarantee a minimal solution
+        if pos.is_done(ctx, stats) {
+            solutions.add(&collect_steps(ctx, &nodes, index));
+            return;
+        }
+
+        SolveStats::count(&mut stats.expanded_per_depth, depth, 1);
+
+        for Successor { step, perm, pos: mut next } in pos.steps(ctx, stats) {
+            let bound = next.lower_bound(ctx);
+            if bound > next.gates_left { continue; }
+
+            let series = pos.built_signals.contains_key(&step.power);
+            let next_cost = so_far + cost.device(step.kind, series, step.merge.is_none());
+
+            match visited.get_key_value(&next) {
+                Some((_, &(_, known_cost))) if known_cost < next_cost => continue,
+                Some((known, &(queued, known_cost))) if known_cost == next_cost => {
+                    //combine with the steps the position was already reached with
+                    let mut seen = known.clone();
+                    let new = seen.merge_lasts(&next.lasts);
+                    if new.is_empty() { continue; }
 
-    length
-}
\ No newline at end of file
+                    visited.remove(&next);
+                    if let Some((_, open)) = &mut positions[queued] {
+                        open.merge_lasts(&new);
+                        visited.insert(seen, (queued, known_cost));
+                        continue;
+                    }
+
+                    //already expanded, expand again for the new steps only
+                    next.lasts = new;
+                    visited.insert(seen, (nodes.len(), next_cost));
+                }
+                Some((_, &(queued, _))) => {
+                    //cheaper than before, the steps it was reached with before don't matter anymore
+                    positions[queued] = None;
+                    visited.remove(&next);
+                    visited.insert(next.clone(), (nodes.len(), next_cost));
+                }
+                None => {
+                    visited.insert(next.clone(), (nodes.len(), next_cost));
+                }
+            }
+
+            nodes.push(Node { parent: index, step: Some(step), perm, alternatives: vec![] });
+            positions.push(Some((next_cost, next)));
+            SolveStats::count(&mut stats.generated_per_depth, depth, 1);
+
+            queue.push((Reverse(next_cost + estimate(bound)), depth + 1, nodes.len() - 1));
+        }
+    }
+}
+
+pub fn main_pathfind<B: BitSet>(query: &Query<B>, max_gates: usize) -> Option<Solution<B>> {
+    let options = SolveOptions { max_devices: max_gates, ..Default::default() };
+    solve(query, &options)
+        .unwrap_or_else(|e| panic!("Invalid query: {}", e))
+        .solution
+}
@@ -1,22 +1,36 @@
-use std::fmt::{Debug, Error, Formatter};
-use std::hash::Hash;
-use std::mem;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display, Error, Formatter};
+use std::hash::{Hash, Hasher};
 use std::ops::{BitAnd, BitOr, BitXor, Not};
 
-use itertools::Itertools;
 use num_traits::{PrimInt, Zero};
 
-pub trait BitSet: Eq + PartialEq + Ord + PartialOrd + Hash +
-Copy + Clone + Debug +
-BitOr<Output=Self> + BitAnd<Output=Self> + BitXor<Output=Self> + Not<Output=Self> + Zero
+use crate::bit::Bit;
+
+/// A set of rows, one bit per row. Implemented for the primitive integers, and in `bitset` for wider tables.
+/// Not necessarily `Copy`, so bitsets that live on the heap are possible as well.
+pub trait BitSet: Eq + PartialEq + Ord + PartialOrd + Hash + Clone + Debug + Send + Sync +
+BitOr<Output=Self> + BitAnd<Output=Self> + BitXor<Output=Self> + Not<Output=Self>
 {
+    /// The maximum number of rows, `usize::MAX` if there is no limit.
     fn size() -> usize;
 
+    fn zero() -> Self;
+
     fn get(&self, index: usize) -> bool;
     fn set(&mut self, index: usize, value: bool);
 
+    /// The bitset with only the first `count` rows set.
+    fn ones(count: usize) -> Self {
+        let mut result = Self::zero();
+        for i in 0..count {
+            result.set(i, true);
+        }
+        result
+    }
+
     fn all_set(&self, mask: Self) -> bool {
-        *self & mask == mask
+        self.clone() & mask.clone() == mask
     }
 
     fn all_ones(&self) -> bool {
@@ -24,13 +38,17 @@ BitOr<Output=Self> + BitAnd<Output=Self> + BitXor<Output=Self> + Not<Output=Self
     }
 }
 
-impl<T: PrimInt + Hash + Debug> BitSet for T {
+impl<T: PrimInt + Hash + Debug + Send + Sync> BitSet for T {
     fn size() -> usize {
-        Self::zero().count_zeros() as usize
+        <T as Zero>::zero().count_zeros() as usize
+    }
+
+    fn zero() -> Self {
+        <T as Zero>::zero()
     }
 
     fn get(&self, index: usize) -> bool {
-        (*self >> index) & T::one() != T::zero()
+        (*self >> index) & T::one() != <T as Zero>::zero()
     }
 
     fn set(&mut self, index: usize, value: bool) {
@@ -41,6 +59,14 @@ impl<T: PrimInt + Hash + Debug> BitSet for T {
             *self = *self & !mask;
         }
     }
+
+    fn ones(count: usize) -> Self {
+        if count == Self::size() {
+            !<T as Zero>::zero()
+        } else {
+            !(!<T as Zero>::zero() << count)
+        }
+    }
 }
 
 /**
@@ -55,94 +81,294 @@ All possible cases:
 * weak pulldown: low=1, high=0, strong=0
 * weak pullup: low=0, high=1, strong=0
 * high impedance: low=0, high=0, strong=0
+* contention: low=1, high=1, strong=1, only produced by `Signal::resolve` and by transistors with a floating gate
 
+A signal has `rows` rows, the bits past those are always 0 so they never influence comparisons or the result
+of `connect`, `pmos` and `nmos`. Combining signals with a different amount of rows is a bug and panics,
+`Query::check` reports it as `QueryError::MismatchedWidths` before a search starts.
 */
-//TODO figure out a way to include the mask in this,
-// so we can ignore meaningless bits without passing a mask around
-// make sure this plays nicely with pmos, nmos, connect and equals
-#[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Signal<B: BitSet> {
     low: B,
     high: B,
     strong: B,
+    /// last so comparisons look at the bits first, and small so it doesn't make the signal much larger
+    rows: u32,
 }
 
 impl<B: BitSet> Signal<B> {
+    /// Parse a signal from a string like `"01↓↑_Z"`, the last character is row 0 and `_` is ignored.
+    /// `L` and `H` can be used instead of `↓` and `↑`, and `X` is contention.
+    /// Panics on invalid input, use `Signal::parse` to get an error instead.
     pub fn from_str(s: &str) -> Signal<B> {
-        let mut result = Signal { low: !B::zero(), high: !B::zero(), strong: !B::zero() };
+        Signal::parse(s).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        for (i, c) in s.chars().rev().filter(|&c| c != '_').enumerate() {
-            assert!(i <= B::size(), "string too large for bitset type");
+    pub fn parse(s: &str) -> Result<Signal<B>, QueryError> {
+        let rows = parse_rows::<B>(s)?;
+        let mut result: Signal<B> = Signal::floating(rows.len());
 
-            let (low, high, strong) = match c {
-                '0' => (true, false, true),
-                '1' => (false, true, true),
-                '↓' => (true, false, false),
-                '↑' => (false, true, false),
-                'Z' => (false, false, false),
-                c => panic!("Unexpected character '{}'", c)
-            };
+        for (i, (position, c)) in rows.into_iter().enumerate() {
+            match row_bits(c) {
+                Some(bits) => result.set_row(i, bits),
+                None => return Err(QueryError::BadCharacter { character: c, position }),
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn set_row(&mut self, row: usize, (low, high, strong): (bool, bool, bool)) {
+        self.low.set(row, low);
+        self.high.set(row, high);
+        self.strong.set(row, strong);
+    }
 
-            result.low.set(i, low);
-            result.high.set(i, high);
-            result.strong.set(i, strong);
+    fn row_char(&self, row: usize) -> char {
+        match (self.low.get(row), self.high.get(row), self.strong.get(row)) {
+            (true, false, true) => '0',
+            (false, true, true) => '1',
+            (true, false, false) => '↓',
+            (false, true, false) => '↑',
+            (false, false, false) => 'Z',
+            (true, true, true) => 'X',
+            _ => 'E',
         }
+    }
 
-        result
+    /// Build a signal with `rows` rows from its bitmasks, the bits past those rows are ignored.
+    pub fn new(rows: usize, low: B, high: B, strong: B) -> Signal<B> {
+        assert!(rows <= B::size(), "{} rows don't fit in bitset type with {} bits", rows, B::size());
+        let mask = B::ones(rows);
+        Signal::new_masked(rows, low & mask.clone(), high & mask.clone(), strong & mask)
+    }
+
+    /// A signal that is strongly driven to `value` in every row.
+    pub fn constant(rows: usize, value: bool) -> Signal<B> {
+        let (low, high) = if value { (B::zero(), B::ones(rows)) } else { (B::ones(rows), B::zero()) };
+        Signal::new_masked(rows, low, high, B::ones(rows))
+    }
+
+    /// A signal that is not driven in any row.
+    pub fn floating(rows: usize) -> Signal<B> {
+        Signal::new_masked(rows, B::zero(), B::zero(), B::zero())
+    }
+
+    /// Like `new`, but the bits past `rows` must already be 0.
+    fn new_masked(rows: usize, low: B, high: B, strong: B) -> Signal<B> {
+        debug_assert!(low.clone() & high.clone() & !strong.clone() == B::zero(), "illegal combination 110");
+        debug_assert!(!low.clone() & !high.clone() & strong.clone() == B::zero(), "illegal combination");
+        debug_assert!((low.clone() | high.clone()) & !B::ones(rows) == B::zero(), "bits set past the last row");
+
+        let rows = u32::try_from(rows).expect("too many rows");
+        Signal { low, high, strong, rows }
     }
 
-    pub fn new(low: B, high: B, strong: B) -> Signal<B> {
-        debug_assert!(low & high & !strong == B::zero(), "illegal combination 110");
-        debug_assert!(!low & !high & strong == B::zero(), "illegal combination");
+    pub fn rows(&self) -> usize {
+        self.rows as usize
+    }
+
+    /// The bitset with every row of this signal set.
+    pub fn row_mask(&self) -> B {
+        B::ones(self.rows())
+    }
 
-        Signal { low, high, strong }
+    /// The value of a single row.
+    pub fn get_row(&self, row: usize) -> Bit {
+        assert!(row < self.rows(), "row {} out of range for signal with {} rows", row, self.rows());
+        Bit::from_levels((self.low.get(row), self.high.get(row), self.strong.get(row)))
+            .expect("illegal combination of bits in signal")
     }
 
-    pub fn connect(a: Signal<B>, b: Signal<B>) -> Option<Signal<B>> {
-        let result = Signal::connect_wrap(a, b);
-//        println!("Connecting {:?} and {:?} gives {:?}", a, b, result);
+    /// Build a signal from the values of its rows, starting at row 0.
+    pub fn from_rows(rows: &[Bit]) -> Signal<B> {
+        assert!(rows.len() <= B::size(), "{} rows don't fit in bitset type with {} bits", rows.len(), B::size());
+        let mut result = Signal::floating(rows.len());
+        for (i, bit) in rows.iter().enumerate() {
+            result.set_row(i, bit.levels());
+        }
         result
     }
 
-    fn connect_wrap(a: Signal<B>, b: Signal<B>) -> Option<Signal<B>> {
-        let ignore = a.high & a.low & a.strong;
+    pub fn connect(a: &Signal<B>, b: &Signal<B>) -> Option<Signal<B>> {
+        assert_same_rows(a, b);
 
-        if a.low & b.high != ignore { return None; };
-        if a.high & b.low != ignore { return None; };
+        if a.low.clone() & b.high.clone() != B::zero() { return None; };
+        if a.high.clone() & b.low.clone() != B::zero() { return None; };
 
-        Some(Signal::new(
-            a.low | b.low,
-            a.high | b.high,
-            a.strong | b.strong,
+        Some(Signal::new_masked(
+            a.rows(),
+            a.low.clone() | b.low.clone(),
+            a.high.clone() | b.high.clone(),
+            a.strong.clone() | b.strong.clone(),
         ))
     }
 
-    pub fn pmos(gate: Signal<B>, drain: Signal<B>) -> Option<Signal<B>> {
-        if gate.strong.all_ones() {
-            Some(Signal::new(
-                gate.low & drain.low,
-                gate.low & drain.high,
-                gate.low & drain.high & drain.strong,
+    pub fn pmos(gate: &Signal<B>, drain: &Signal<B>) -> Option<Signal<B>> {
+        assert_same_rows(gate, drain);
+
+        if gate.is_strong() {
+            Some(Signal::new_masked(
+                gate.rows(),
+                gate.low.clone() & drain.low.clone(),
+                gate.low.clone() & drain.high.clone(),
+                gate.low.clone() & drain.high.clone() & drain.strong.clone(),
             ))
         } else {
             None
         }
     }
 
-    pub fn nmos(gate: Signal<B>, drain: Signal<B>) -> Option<Signal<B>> {
-        if gate.strong.all_ones() {
-            Some(Signal::new(
-                gate.high & drain.low,
-                gate.high & drain.high,
-                gate.high & drain.low & drain.strong,
+    /// An NMOS with gate `gate` in parallel with a PMOS with the complement of `gate`, passes `drain` unchanged
+    /// where `gate` is high.
+    pub fn transmission_gate(gate: &Signal<B>, drain: &Signal<B>) -> Option<Signal<B>> {
+        assert_same_rows(gate, drain);
+
+        if gate.is_strong() {
+            Some(Signal::new_masked(
+                gate.rows(),
+                gate.high.clone() & drain.low.clone(),
+                gate.high.clone() & drain.high.clone(),
+                gate.high.clone() & drain.strong.clone(),
             ))
         } else {
             None
         }
     }
 
-    pub fn ignored_mask(&self) -> B {
-        self.low & self.high & self.strong
+    /// Like `pmos`, but `gate` doesn't need to be strong. A weak low still turns the transistor on but the output
+    /// is weak, and in rows where the gate is floating or `X` a driven `drain` gives `X`.
+    pub fn pmos_weak_gate(gate: &Signal<B>, drain: &Signal<B>) -> Signal<B> {
+        let on = gate.low.clone() & !gate.high.clone();
+        Signal::switch(gate, on, drain, drain.high.clone() & drain.strong.clone())
+    }
+
+    /// Like `nmos`, but `gate` doesn't need to be strong, see `pmos_weak_gate`.
+    pub fn nmos_weak_gate(gate: &Signal<B>, drain: &Signal<B>) -> Signal<B> {
+        let on = gate.high.clone() & !gate.low.clone();
+        Signal::switch(gate, on, drain, drain.low.clone() & drain.strong.clone())
+    }
+
+    /// Like `transmission_gate`, but `gate` doesn't need to be strong, see `pmos_weak_gate`.
+    pub fn transmission_gate_weak_gate(gate: &Signal<B>, drain: &Signal<B>) -> Signal<B> {
+        let on = gate.high.clone() & !gate.low.clone();
+        Signal::switch(gate, on, drain, drain.strong.clone())
+    }
+
+    /// Pass `drain` in the rows that are `on`, strongly only where `gate` is strong and `passes_strong` is set.
+    fn switch(gate: &Signal<B>, on: B, drain: &Signal<B>, passes_strong: B) -> Signal<B> {
+        assert_same_rows(gate, drain);
+
+        let driven = drain.low.clone() | drain.high.clone();
+        let gate_unknown = !(gate.low.clone() | gate.high.clone()) | gate.contention();
+        let unknown = (gate_unknown & driven) | (on.clone() & drain.contention());
+
+        Signal::new_masked(
+            gate.rows(),
+            (on.clone() & drain.low.clone()) | unknown.clone(),
+            (on.clone() & drain.high.clone()) | unknown.clone(),
+            (on & gate.strong.clone() & passes_strong) | unknown,
+        )
+    }
+
+    /// A weak copy of `power`, the output of a resistor. Contention stays `X`.
+    pub fn resistor(power: &Signal<B>) -> Signal<B> {
+        Signal::new_masked(power.rows(), power.low.clone(), power.high.clone(), power.contention())
+    }
+
+    /// The rows where one signal is high and the other one is low.
+    pub fn conflicts(&self, other: &Signal<B>) -> B {
+        (self.low.clone() & other.high.clone()) | (self.high.clone() & other.low.clone())
+    }
+
+    /// Like `connect`, but a strong level overrides a weak level of the other signal instead of failing.
+    /// Two strong or two weak signals with different levels still can't be connected.
+    pub fn connect_ratioed(a: &Signal<B>, b: &Signal<B>) -> Option<Signal<B>> {
+        let result = Signal::resolve(a, b);
+        if result.contention() == B::zero() { Some(result) } else { None }
+    }
+
+    /// `resolve` wrapped in `Some`, to be used where connecting can fail.
+    pub fn connect_contention(a: &Signal<B>, b: &Signal<B>) -> Option<Signal<B>> {
+        Some(Signal::resolve(a, b))
+    }
+
+    /// Connect two signals without ever failing: where they pull to different levels the strong one wins,
+    /// and if they are equally strong the row becomes `X`. Rows that are `X` in either signal stay `X`.
+    pub fn resolve(a: &Signal<B>, b: &Signal<B>) -> Signal<B> {
+        assert_same_rows(a, b);
+
+        let conflicts = a.conflicts(b) | a.contention() | b.contention();
+        let a_wins = conflicts.clone() & a.strong.clone() & !b.strong.clone();
+        let b_wins = conflicts.clone() & b.strong.clone() & !a.strong.clone();
+        let contention = conflicts.clone() & !(a_wins.clone() | b_wins.clone());
+
+        let level = |a_level: &B, b_level: &B| {
+            ((a_level.clone() | b_level.clone()) & !conflicts.clone()) |
+                (a_level.clone() & a_wins.clone()) | (b_level.clone() & b_wins.clone()) | contention.clone()
+        };
+        Signal::new_masked(
+            a.rows(),
+            level(&a.low, &b.low),
+            level(&a.high, &b.high),
+            a.strong.clone() | b.strong.clone() | contention.clone(),
+        )
+    }
+
+    /// The rows that are `X`, pulled both high and low.
+    pub fn contention(&self) -> B {
+        self.low.clone() & self.high.clone()
+    }
+
+    /// Swap the high and low levels, keeping the strength.
+    pub fn complement(&self) -> Signal<B> {
+        Signal { low: self.high.clone(), high: self.low.clone(), strong: self.strong.clone(), rows: self.rows }
+    }
+
+    pub fn nmos(gate: &Signal<B>, drain: &Signal<B>) -> Option<Signal<B>> {
+        assert_same_rows(gate, drain);
+
+        if gate.is_strong() {
+            Some(Signal::new_masked(
+                gate.rows(),
+                gate.high.clone() & drain.low.clone(),
+                gate.high.clone() & drain.high.clone(),
+                gate.high.clone() & drain.low.clone() & drain.strong.clone(),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Whether this signal is strongly driven to a single level in every row.
+    pub fn is_strong(&self) -> bool {
+        self.strong == self.row_mask() && self.contention() == B::zero()
+    }
+
+    pub fn is_high(&self, row: usize) -> bool {
+        self.high.get(row)
+    }
+
+    /// Move row `i` to row `perm[i]`, rows past the end of `perm` are left in place.
+    /// `perm` must not move rows past the last row of this signal.
+    pub fn permute_rows(&self, perm: &[usize]) -> Signal<B> {
+        let mut result = self.clone();
+        for (from, &to) in perm.iter().enumerate() {
+            if from == to { continue; }
+            result.low.set(to, self.low.get(from));
+            result.high.set(to, self.high.get(from));
+            result.strong.set(to, self.strong.get(from));
+        }
+        result
+    }
+}
+
+/// Signals that are compared are almost always the same width, so only the bits are hashed.
+impl<B: BitSet> Hash for Signal<B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.low.hash(state);
+        self.high.hash(state);
+        self.strong.hash(state);
     }
 }
 
@@ -150,23 +376,15 @@ impl<B: BitSet> Debug for Signal<B> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         if f.alternate() {
             f.debug_struct("Signal")
+                .field("rows", &self.rows)
                 .field("low", &self.low)
                 .field("high", &self.high)
                 .field("strong", &self.strong)
                 .finish()?
         } else {
             write!(f, "[")?;
-            for index in (0..8 * mem::size_of::<B>()).rev() {
-                let char = match (self.low.get(index), self.high.get(index), self.strong.get(index)) {
-                    (true, false, true) => '0',
-                    (false, true, true) => '1',
-                    (true, false, false) => '↓',
-                    (false, true, false) => '↑',
-                    (false, false, false) => 'Z',
-                    (true, true, true) => continue, //masked
-                    _ => 'E',
-                };
-                write!(f, "{}", char)?;
+            for index in (0..self.rows()).rev() {
+                write!(f, "{}", self.row_char(index))?;
             }
             write!(f, "]")?;
         }
@@ -174,21 +392,169 @@ impl<B: BitSet> Debug for Signal<B> {
     }
 }
 
-#[derive(Debug)]
+/// How the strength of a signal is compared in `CareSignal::matches`, the levels always have to match.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MatchMode {
+    /// the strength has to be the same, a `1` doesn't match `↑` and a `↑` doesn't match `1`
+    Exact,
+    /// only the level matters, so both `1` and `↑` match either of them
+    Level,
+    /// only the level matters, but every driven row has to be strong
+    Strong,
+}
+
+/// A signal that only has to match `signal` in the `care` rows.
+/// The `any_strong` rows don't care about the level, as long as it's strongly driven.
+#[derive(Clone, Eq, PartialEq)]
 pub struct CareSignal<B: BitSet> {
     pub signal: Signal<B>,
     pub care: B,
+    pub any_strong: B,
+    pub mode: MatchMode,
 }
 
 impl<B: BitSet> CareSignal<B> {
-    pub const fn new(signal: Signal<B>, care: B) -> CareSignal<B> {
-        CareSignal { signal, care }
+    pub fn new(signal: Signal<B>, care: B) -> CareSignal<B> {
+        CareSignal { signal, care, any_strong: B::zero(), mode: MatchMode::Exact }
+    }
+
+    pub fn with_mode(self, mode: MatchMode) -> CareSignal<B> {
+        CareSignal { mode, ..self }
+    }
+
+    /// Parse a signal like `Signal::from_str`, with `-` or `X` for rows we don't care about and `?` for rows
+    /// that can be either `0` or `1`. An output is never supposed to be in contention, so unlike in a `Signal`
+    /// `X` doesn't mean contention here. Panics on invalid input, use `CareSignal::parse` to get an error instead.
+    pub fn from_str(s: &str) -> CareSignal<B> {
+        CareSignal::parse(s).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn parse(s: &str) -> Result<CareSignal<B>, QueryError> {
+        let rows = parse_rows::<B>(s)?;
+        let mut signal: Signal<B> = Signal::floating(rows.len());
+        let mut care = B::zero();
+        let mut any_strong = B::zero();
+
+        for (i, (position, c)) in rows.into_iter().enumerate() {
+            match (c, row_bits(c)) {
+                ('-', _) | ('X', _) => {}
+                ('?', _) => any_strong.set(i, true),
+                (_, Some(bits)) => {
+                    signal.set_row(i, bits);
+                    care.set(i, true);
+                }
+                (character, None) => return Err(QueryError::BadCharacter { character, position }),
+            }
+        }
+
+        Ok(CareSignal { signal, care, any_strong, mode: MatchMode::Exact })
+    }
+
+    pub fn permute_rows(&self, perm: &[usize]) -> CareSignal<B> {
+        let mut care = self.care.clone();
+        let mut any_strong = self.any_strong.clone();
+        for (from, &to) in perm.iter().enumerate() {
+            care.set(to, self.care.get(from));
+            any_strong.set(to, self.any_strong.get(from));
+        }
+        CareSignal { signal: self.signal.permute_rows(perm), care, any_strong, mode: self.mode }
+    }
+
+    pub fn matches(&self, signal: &Signal<B>) -> bool {
+        let differs = |a: &B, b: &B| (a.clone() ^ b.clone()) & self.care.clone() != B::zero();
+        let strength_ok = match self.mode {
+            MatchMode::Exact => !differs(&self.signal.strong, &signal.strong),
+            MatchMode::Level => true,
+            MatchMode::Strong => signal.strong.all_set(self.driven()),
+        };
+
+        self.signal.rows == signal.rows &&
+            !differs(&self.signal.low, &signal.low) &&
+            !differs(&self.signal.high, &signal.high) &&
+            strength_ok &&
+            signal.strong.all_set(self.any_strong.clone())
+    }
+
+    /// Whether a signal matches this one exactly when it's equal to `signal`, so it can be looked up directly.
+    pub fn is_exact(&self) -> bool {
+        self.mode == MatchMode::Exact && self.care == self.signal.row_mask()
+    }
+
+    /// The rows we care about where `signal` is driven high or low.
+    fn driven(&self) -> B {
+        (self.signal.low.clone() | self.signal.high.clone()) & self.care.clone()
+    }
+
+    /// A lower bound on the number of devices whose outputs still need to be connected to `base` to match this
+    /// signal, or `None` if that's impossible. `connect` can only add bits, so `base` must not have any bits the
+    /// target doesn't have, except for weak levels that can still be overridden if `ratioed` connections are
+    /// allowed. Strong highs can only come from a PMOS and strong lows only from an NMOS, a transmission gate can
+    /// pass both but counts as two devices. Contention rows are left out of that, a single transistor with a
+    /// floating gate already gives `X`.
+    pub fn devices_needed(&self, base: &Signal<B>, ratioed: bool) -> Option<usize> {
+        //bits set in `a` but not in `b`, in the rows we care about
+        let minus = |a: &B, b: &B| a.clone() & !b.clone() & self.care.clone();
+        let target = &self.signal;
+
+        //only exact matching is hurt by strong bits the target doesn't have
+        let (required_strong, extra_strong) = match self.mode {
+            MatchMode::Exact => (target.strong.clone() & self.care.clone(), minus(&base.strong, &target.strong)),
+            MatchMode::Level => (B::zero(), B::zero()),
+            MatchMode::Strong => (self.driven(), B::zero()),
+        };
+
+        let overridable = match (ratioed, self.mode) {
+            (false, _) => B::zero(),
+            (true, MatchMode::Exact) => !base.strong.clone() & target.strong.clone(),
+            (true, _) => !base.strong.clone(),
+        };
+
+        let extra = ((minus(&base.low, &target.low) | minus(&base.high, &target.high)) & !overridable) | extra_strong;
+        if extra != B::zero() {
+            return None;
+        }
+
+        let contention = target.low.clone() & target.high.clone();
+        let missing_strong = required_strong & !base.strong.clone() & !contention;
+        let missing_any = minus(&target.low, &base.low) | minus(&target.high, &base.high) |
+            (self.any_strong.clone() & !base.strong.clone());
+
+        let pmos = (missing_strong.clone() & target.high.clone() != B::zero()) as usize;
+        let nmos = (missing_strong & target.low.clone() != B::zero()) as usize;
+
+        let strong = pmos + nmos;
+        if strong == 0 && missing_any != B::zero() {
+            Some(1)
+        } else {
+            Some(strong)
+        }
     }
+}
 
-    pub fn matches(&self, signal: Signal<B>) -> bool {
-        (((self.signal.low ^ signal.low) & self.care) == B::zero()) &&
-            (((self.signal.high ^ signal.high) & self.care) == B::zero()) &&
-            (((self.signal.strong ^ signal.strong) & self.care) == B::zero())
+impl<B: BitSet> Debug for CareSignal<B> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        if f.alternate() {
+            f.debug_struct("CareSignal")
+                .field("signal", &self.signal)
+                .field("care", &self.care)
+                .field("any_strong", &self.any_strong)
+                .field("mode", &self.mode)
+                .finish()?
+        } else {
+            write!(f, "[")?;
+            for index in (0..self.signal.rows()).rev() {
+                let char = if self.care.get(index) {
+                    self.signal.row_char(index)
+                } else if self.any_strong.get(index) {
+                    '?'
+                } else {
+                    '-'
+                };
+                write!(f, "{}", char)?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
     }
 }
 
@@ -204,16 +570,98 @@ pub struct Query<'a, B: BitSet> {
 }
 
 impl<'a, B: BitSet> Query<'a, B> {
-    pub fn check(&self) {
-        assert!(
-            self.power.iter()
-                .chain(self.inputs.iter())
-                .chain(self.outputs.iter().map(|cs| &cs.signal))
-                .map(|&s| s.ignored_mask())
-                .all_equal(),
-            "All signals must have the same ignored mask"
-        );
-
-        //TODO check that ouput cares are a subset of !ignored_mask()
-    }
-}
\ No newline at end of file
+    pub fn check(&self) -> Result<(), QueryError> {
+        if self.power.is_empty() {
+            return Err(QueryError::EmptyPower);
+        }
+
+        let rows = self.rows();
+        let mut all = self.power.iter()
+            .chain(self.inputs.iter())
+            .chain(self.outputs.iter().map(|cs| &cs.signal));
+        if let Some(signal) = all.find(|s| s.rows() != rows) {
+            return Err(QueryError::MismatchedWidths { expected: rows, found: signal.rows() });
+        }
+
+        if let Some(input) = self.inputs.iter().position(|s| !s.is_strong()) {
+            return Err(QueryError::NonStrongInput { input });
+        }
+
+        let mask = B::ones(rows);
+        let outside = |cs: &CareSignal<B>| !mask.all_set(cs.care.clone() | cs.any_strong.clone());
+        if let Some(output) = self.outputs.iter().position(outside) {
+            return Err(QueryError::CareOutsideRows { output });
+        }
+
+        Ok(())
+    }
+
+    /// The number of rows in the signals of this query, taken from the first power signal.
+    pub fn rows(&self) -> usize {
+        self.power.first().map_or(0, |s| s.rows())
+    }
+}
+
+/// The characters of `s` with their char position, starting from row 0 at the end of the string.
+fn parse_rows<B: BitSet>(s: &str) -> Result<Vec<(usize, char)>, QueryError> {
+    let rows: Vec<(usize, char)> = s.chars().enumerate().filter(|&(_, c)| c != '_').collect();
+    if rows.len() > B::size() {
+        return Err(QueryError::StringTooLong { length: rows.len(), max: B::size() });
+    }
+    Ok(rows.into_iter().rev().collect())
+}
+
+/// The low, high and strong bits of a row character in a `Signal` string.
+fn row_bits(c: char) -> Option<(bool, bool, bool)> {
+    match c {
+        '0' => Some((true, false, true)),
+        '1' => Some((false, true, true)),
+        '↓' | 'L' => Some((true, false, false)),
+        '↑' | 'H' => Some((false, true, false)),
+        'Z' => Some((false, false, false)),
+        'X' => Some((true, true, true)),
+        _ => None,
+    }
+}
+
+fn assert_same_rows<B: BitSet>(a: &Signal<B>, b: &Signal<B>) {
+    assert_eq!(a.rows, b.rows, "can't combine signals with a different amount of rows");
+}
+
+/// Reasons a `Query` or one of its signals can be invalid.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum QueryError {
+    /// not all signals have the same amount of rows
+    MismatchedWidths { expected: usize, found: usize },
+    /// `query.outputs[output].care` has bits set outside of the valid rows
+    CareOutsideRows { output: usize },
+    /// there are no power signals to build devices from
+    EmptyPower,
+    /// `query.inputs[input]` is not strong in every row, so it can't be used as a gate
+    NonStrongInput { input: usize },
+    /// the string has more rows than fit in the bitset type
+    StringTooLong { length: usize, max: usize },
+    /// unexpected character at the given char position in the string
+    BadCharacter { character: char, position: usize },
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            QueryError::MismatchedWidths { expected, found } =>
+                write!(f, "All signals must have the same width, expected {} rows but found {}", expected, found),
+            QueryError::CareOutsideRows { output } =>
+                write!(f, "Output {} cares about rows outside of the valid rows", output),
+            QueryError::EmptyPower =>
+                write!(f, "At least one power signal is required"),
+            QueryError::NonStrongInput { input } =>
+                write!(f, "Input {} must be strong in every row to be used as a gate", input),
+            QueryError::StringTooLong { length, max } =>
+                write!(f, "String with {} rows too large for bitset type with {} bits", length, max),
+            QueryError::BadCharacter { character, position } =>
+                write!(f, "Unexpected character '{}' at position {}", character, position),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
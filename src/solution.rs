@@ -0,0 +1,398 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error, Formatter};
+
+use crate::signal::{BitSet, Query, Signal};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone, Debug)]
+pub enum Kind {
+    PMOS,
+    NMOS,
+    /// an NMOS and a PMOS in parallel, the gate is the gate of the NMOS and the PMOS gets its complement
+    TransmissionGate,
+    /// passes its power signal weakly, has no gate
+    Resistor,
+    /// an always-on weak PMOS from a power signal that is high in every row, its gate is tied low
+    PullUp,
+    /// an always-on weak NMOS from a power signal that is low in every row, its gate is tied high
+    PullDown,
+}
+
+impl Kind {
+    pub fn has_gate(self) -> bool {
+        matches!(self, Kind::PMOS | Kind::NMOS | Kind::TransmissionGate)
+    }
+
+    /// The size of a device of this kind as counted by `SolveOptions::max_devices`: a transmission gate is placed
+    /// as one unit, but it is still two transistors.
+    pub fn size(self) -> usize {
+        match self {
+            Kind::TransmissionGate => 2,
+            _ => 1,
+        }
+    }
+
+    /// The output of this device, `gate` must be given exactly when the kind has one. Gates that are not strong
+    /// in every row give `None` unless `weak_gates` is set.
+    pub fn apply<B: BitSet>(self, gate: Option<&Signal<B>>, power: &Signal<B>, weak_gates: bool) -> Option<Signal<B>> {
+        match (self, gate) {
+            (Kind::PMOS, Some(gate)) if weak_gates => Some(Signal::pmos_weak_gate(gate, power)),
+            (Kind::NMOS, Some(gate)) if weak_gates => Some(Signal::nmos_weak_gate(gate, power)),
+            (Kind::TransmissionGate, Some(gate)) if weak_gates => {
+                Some(Signal::transmission_gate_weak_gate(gate, power))
+            }
+            (Kind::PMOS, Some(gate)) => Signal::pmos(gate, power),
+            (Kind::NMOS, Some(gate)) => Signal::nmos(gate, power),
+            (Kind::TransmissionGate, Some(gate)) => Signal::transmission_gate(gate, power),
+            (Kind::Resistor, None) => Some(Signal::resistor(power)),
+            (Kind::PullUp, None) if *power == Signal::constant(power.rows(), true) => Some(Signal::resistor(power)),
+            (Kind::PullDown, None) if *power == Signal::constant(power.rows(), false) => Some(Signal::resistor(power)),
+            _ => None,
+        }
+    }
+}
+
+/// The cost of the devices in a circuit, used by `Strategy::UniformCost` and `Strategy::AStar`.
+/// The default gives every transistor a cost of 1, so the cheapest circuit is the one with the fewest devices.
+/// A transmission gate costs as much as its two transistors, pull-ups and pull-downs as much as a PMOS and an NMOS.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CostModel {
+    pub pmos: u32,
+    pub nmos: u32,
+    pub resistor: u32,
+    /// added for every device whose source is an internal net instead of a power signal,
+    /// ie. every device that is not at the bottom of a series stack
+    pub series: u32,
+    /// added for every internal net, the contacts a device shares with an existing net are free
+    pub net: u32,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel { pmos: 1, nmos: 1, resistor: 1, series: 0, net: 0 }
+    }
+}
+
+impl CostModel {
+    /// The cost of a single device, `series` if its source is an internal net and `new_net` if its drain is not
+    /// connected to any other device.
+    pub fn device(&self, kind: Kind, series: bool, new_net: bool) -> u32 {
+        let kind_cost = match kind {
+            Kind::PMOS => self.pmos,
+            Kind::NMOS => self.nmos,
+            Kind::TransmissionGate => self.pmos + self.nmos,
+            Kind::Resistor => self.resistor,
+            Kind::PullUp => self.pmos,
+            Kind::PullDown => self.nmos,
+        };
+        kind_cost + if series { self.series } else { 0 } + if new_net { self.net } else { 0 }
+    }
+
+    /// A lower bound on the cost of a single device.
+    pub fn min_device(&self) -> u32 {
+        self.pmos.min(self.nmos).min(self.resistor)
+    }
+}
+
+pub type NetId = usize;
+
+/// Where the value on a net comes from.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum NetOrigin {
+    /// `query.power[index]`
+    Power(usize),
+    /// `query.inputs[index]`
+    Input(usize),
+    /// driven by one or more devices in the solution
+    Internal,
+}
+
+#[derive(Clone, Debug)]
+pub struct Net<B: BitSet> {
+    pub name: String,
+    pub origin: NetOrigin,
+    /// the final value of this net, after all merges
+    pub signal: Signal<B>,
+    /// indices into `Solution::devices` of the devices whose drain is this net
+    pub drivers: Vec<usize>,
+}
+
+impl<B: BitSet> Net<B> {
+    /// Whether multiple device outputs were merged into this net using `Signal::connect`.
+    pub fn is_merged(&self) -> bool {
+        self.drivers.len() > 1
+    }
+}
+
+/// A single transistor or transmission gate, `source` is the side connected to the power candidate it was built
+/// from.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub struct Device {
+    pub kind: Kind,
+    pub source: NetId,
+    /// `None` for devices without a gate
+    pub gate: Option<NetId>,
+    /// the gate of the PMOS in a transmission gate, which has the complement of `gate`
+    pub inverted_gate: Option<NetId>,
+    pub drain: NetId,
+}
+
+impl Device {
+    /// The transistors this device consists of.
+    pub fn transistors(&self) -> Vec<Device> {
+        match self.inverted_gate {
+            Some(inverted_gate) if self.kind == Kind::TransmissionGate => vec![
+                Device { kind: Kind::NMOS, inverted_gate: None, ..*self },
+                Device { kind: Kind::PMOS, gate: Some(inverted_gate), inverted_gate: None, ..*self },
+            ],
+            _ => vec![*self],
+        }
+    }
+}
+
+/// A single search step expressed in signals: a device is built from `power` and `gate`, and its output is
+/// either added as a new net or merged into the existing free net `merge`. `output` is the resulting value.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Debug)]
+pub struct Step<B: BitSet> {
+    pub kind: Kind,
+    pub power: Signal<B>,
+    pub gate: Option<Signal<B>>,
+    pub merge: Option<Signal<B>>,
+    pub output: Signal<B>,
+}
+
+impl<B: BitSet> Step<B> {
+    /// The signal on the PMOS gate of a transmission gate.
+    pub fn inverted_gate(&self) -> Option<Signal<B>> {
+        match self.kind {
+            Kind::TransmissionGate => self.gate.as_ref().map(|g| g.complement()),
+            _ => None,
+        }
+    }
+
+    pub fn permute_rows(&self, perm: &[usize]) -> Step<B> {
+        Step {
+            kind: self.kind,
+            power: self.power.permute_rows(perm),
+            gate: self.gate.as_ref().map(|g| g.permute_rows(perm)),
+            merge: self.merge.as_ref().map(|s| s.permute_rows(perm)),
+            output: self.output.permute_rows(perm),
+        }
+    }
+}
+
+/// A transistor netlist that implements a `Query`.
+#[derive(Clone, Debug)]
+pub struct Solution<B: BitSet> {
+    pub nets: Vec<Net<B>>,
+    pub devices: Vec<Device>,
+    /// for each `query.outputs[i]` the net that implements it
+    pub outputs: Vec<NetId>,
+}
+
+impl<B: BitSet> Solution<B> {
+    /// Replay the given steps on top of the nets in `query` to build the netlist.
+    pub fn from_steps(query: &Query<B>, steps: &[Step<B>]) -> Solution<B> {
+        let mut nets = Vec::new();
+
+        for (i, signal) in query.power.iter().enumerate() {
+            let signal = signal.clone();
+            let name = if signal == Signal::constant(signal.rows(), true) {
+                "vdd".to_string()
+            } else if signal == Signal::constant(signal.rows(), false) {
+                "gnd".to_string()
+            } else {
+                format!("pwr{}", i)
+            };
+            nets.push(Net { name, origin: NetOrigin::Power(i), signal, drivers: vec![] });
+        }
+        for (i, signal) in query.inputs.iter().enumerate() {
+            nets.push(Net { name: input_name(i), origin: NetOrigin::Input(i), signal: signal.clone(), drivers: vec![] });
+        }
+
+        //mirrors Pos: built signals take precedence over the query signals
+        let mut built: BTreeMap<Signal<B>, NetId> = BTreeMap::new();
+        let find = |built: &BTreeMap<Signal<B>, NetId>, signal: &Signal<B>, cands: &[Signal<B>], offset: usize| {
+            built.get(signal).copied()
+                .or_else(|| cands.iter().position(|s| s == signal).map(|i| i + offset))
+                .expect("step refers to unknown signal")
+        };
+
+        let mut devices = Vec::new();
+        for step in steps {
+            let source = find(&built, &step.power, query.power, 0);
+            let gate = step.gate.as_ref().map(|g| find(&built, g, query.inputs, query.power.len()));
+            let inverted_gate = step.inverted_gate().map(|g| find(&built, &g, query.inputs, query.power.len()));
+
+            let drain = match &step.merge {
+                Some(merge) => built.remove(merge).expect("merging with unknown signal"),
+                None => {
+                    nets.push(Net {
+                        name: String::new(),
+                        origin: NetOrigin::Internal,
+                        signal: step.output.clone(),
+                        drivers: vec![],
+                    });
+                    nets.len() - 1
+                }
+            };
+
+            nets[drain].signal = step.output.clone();
+            nets[drain].drivers.push(devices.len());
+            built.insert(step.output.clone(), drain);
+
+            devices.push(Device { kind: step.kind, source, gate, inverted_gate, drain });
+        }
+
+        let outputs: Vec<NetId> = query.outputs.iter().map(|cs| {
+            built.iter()
+                .find(|(s, _)| cs.matches(s))
+                .map(|(_, &net)| net)
+                .expect("solution does not implement all outputs")
+        }).collect();
+
+        let mut next_internal = 0;
+        for (id, net) in nets.iter_mut().enumerate() {
+            if net.origin != NetOrigin::Internal { continue; }
+
+            net.name = match outputs.iter().position(|&o| o == id) {
+                Some(_) if outputs.len() == 1 => "y".to_string(),
+                Some(i) => format!("y{}", i),
+                None => {
+                    next_internal += 1;
+                    format!("n{}", next_internal)
+                }
+            };
+        }
+
+        Solution { nets, devices, outputs }
+    }
+
+    /// The number of devices, a transmission gate counts as its two transistors.
+    pub fn device_count(&self) -> usize {
+        self.devices.iter().map(|d| d.kind.size()).sum()
+    }
+
+    /// All devices with the transmission gates split into their two transistors.
+    pub fn transistors(&self) -> Vec<Device> {
+        self.devices.iter().flat_map(|d| d.transistors()).collect()
+    }
+
+    /// The largest number of devices in series between a power signal and a net.
+    pub fn max_series(&self) -> usize {
+        //devices only use nets built by earlier devices as their source
+        let mut series = vec![0; self.nets.len()];
+        for d in &self.devices {
+            series[d.drain] = series[d.drain].max(series[d.source] + 1);
+        }
+        series.into_iter().max().unwrap_or(0)
+    }
+
+    pub fn cost(&self, model: &CostModel) -> u32 {
+        self.devices.iter().enumerate().map(|(i, d)| {
+            let series = self.nets[d.source].origin == NetOrigin::Internal;
+            let new_net = self.nets[d.drain].drivers[0] == i;
+            model.device(d.kind, series, new_net)
+        }).sum()
+    }
+
+    /// The nets where a strong driver overrides a weak one of the opposite level, with the rows where that
+    /// happens. Current flows from one power signal to another through these nets in those rows.
+    pub fn static_current(&self) -> Vec<(NetId, B)> {
+        //a net can't be merged into after it's used, so devices saw the final signals of their source and gate
+        let mut result = Vec::new();
+        for (id, net) in self.nets.iter().enumerate() {
+            if !net.is_merged() { continue; }
+
+            let rows = net.drivers.iter().fold(B::zero(), |rows, &d| {
+                let device = &self.devices[d];
+                let gate = device.gate.map(|g| &self.nets[g].signal);
+                //the weak gate versions give the same output for strong gates
+                let output = device.kind.apply(gate, &self.nets[device.source].signal, true)
+                    .expect("device in solution can't be applied");
+                rows | output.conflicts(&net.signal)
+            });
+            if rows != B::zero() {
+                result.push((id, rows));
+            }
+        }
+        result
+    }
+
+    /// The nets that are `X` in some rows because of a fight between equally strong drivers or a floating gate,
+    /// with those rows.
+    pub fn contention(&self) -> Vec<(NetId, B)> {
+        self.nets.iter().enumerate()
+            .map(|(id, net)| (id, net.signal.contention()))
+            .filter(|(_, rows)| *rows != B::zero())
+            .collect()
+    }
+
+    pub fn net_name(&self, net: NetId) -> &str {
+        &self.nets[net].name
+    }
+
+    /// Equal for solutions that are the same up to renaming the internal nets. Nets are identified by their signal,
+    /// so permuting the rows with a symmetry of the query also renames the inputs that are swapped by it.
+    /// Source and drain are interchangeable.
+    pub(crate) fn key(&self, perm: &[usize]) -> SolutionKey<B> {
+        let net = |id: NetId| {
+            let net = &self.nets[id];
+            (net.origin == NetOrigin::Internal, net.signal.permute_rows(perm))
+        };
+
+        let mut devices: Vec<_> = self.devices.iter().map(|d| {
+            let (source, drain) = (net(d.source), net(d.drain));
+            let (first, second) = if source <= drain { (source, drain) } else { (drain, source) };
+            (d.kind, d.gate.map(net), first, second)
+        }).collect();
+        devices.sort_unstable();
+
+        (devices, self.outputs.iter().map(|&o| net(o)).collect())
+    }
+}
+
+/// A net in `SolutionKey`: whether it is internal and the signal on it.
+type NetKey<B> = (bool, Signal<B>);
+pub(crate) type SolutionKey<B> = (Vec<(Kind, Option<NetKey<B>>, NetKey<B>, NetKey<B>)>, Vec<NetKey<B>>);
+
+fn input_name(index: usize) -> String {
+    if index < 26 {
+        ((b'a' + index as u8) as char).to_string()
+    } else {
+        format!("in{}", index)
+    }
+}
+
+impl<B: BitSet> Display for Solution<B> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        for device in &self.devices {
+            write!(f, "{:?} source={}", device.kind, self.net_name(device.source))?;
+            if let Some(gate) = device.gate {
+                write!(f, " gate={}", self.net_name(gate))?;
+            }
+            if let Some(inverted_gate) = device.inverted_gate {
+                write!(f, " inverted_gate={}", self.net_name(inverted_gate))?;
+            }
+            writeln!(f, " drain={}", self.net_name(device.drain))?;
+        }
+        for net in &self.nets {
+            if net.is_merged() {
+                writeln!(f, "merged {} = {:?}", net.name, net.signal)?;
+            }
+        }
+        let row_list = |net: NetId, rows: B| -> Vec<usize> {
+            (0..self.nets[net].signal.rows()).filter(|&r| rows.get(r)).collect()
+        };
+        for (net, rows) in self.static_current() {
+            writeln!(f, "static current {} in rows {:?}", self.net_name(net), row_list(net, rows))?;
+        }
+        for (net, rows) in self.contention() {
+            writeln!(f, "contention {} in rows {:?}", self.net_name(net), row_list(net, rows))?;
+        }
+        for (i, &net) in self.outputs.iter().enumerate() {
+            writeln!(f, "output {} = {} {:?}", i, self.net_name(net), self.nets[net].signal)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use crate::signal::{BitSet, Query, Signal};
+
+/// Groups larger than this are not worth the cost of canonicalizing every position.
+const MAX_GROUP_SIZE: usize = 5040;
+
+/// The group of row permutations that leave a query unchanged, generated by swapping interchangeable inputs
+/// (eg. the inputs of a NAND gate). A permutation maps row `i` to row `perm[i]`, the first one is the identity.
+#[derive(Debug, Clone)]
+pub struct Symmetries {
+    perms: Vec<Vec<usize>>,
+    inverses: Vec<usize>,
+    /// the index of each permutation in `perms`
+    index: HashMap<Vec<usize>, usize>,
+}
+
+impl Symmetries {
+    pub fn none() -> Symmetries {
+        Symmetries { perms: vec![vec![]], inverses: vec![0], index: HashMap::new() }
+    }
+
+    pub fn detect<B: BitSet>(query: &Query<B>) -> Symmetries {
+        let rows = query.rows();
+
+        //every row needs a distinct combination of inputs to know where it should go
+        let row_of: HashMap<Vec<bool>, usize> = (0..rows)
+            .map(|r| (query.inputs.iter().map(|s| s.is_high(r)).collect(), r))
+            .collect();
+        if row_of.len() != rows {
+            return Symmetries::none();
+        }
+
+        let mut generators = Vec::new();
+        for i in 0..query.inputs.len() {
+            for j in (i + 1)..query.inputs.len() {
+                let perm: Option<Vec<usize>> = (0..rows).map(|r| {
+                    let mut key: Vec<bool> = query.inputs.iter().map(|s| s.is_high(r)).collect();
+                    key.swap(i, j);
+                    row_of.get(&key).copied()
+                }).collect();
+
+                if let Some(perm) = perm {
+                    if is_symmetry(query, &perm, i, j) {
+                        generators.push(perm);
+                    }
+                }
+            }
+        }
+
+        let identity: Vec<usize> = (0..rows).collect();
+        let mut perms = vec![identity.clone()];
+        let mut index = HashMap::new();
+        index.insert(identity.clone(), 0);
+        let mut todo = vec![identity];
+        while let Some(perm) = todo.pop() {
+            for generator in &generators {
+                let next = compose(generator, &perm);
+                if !index.contains_key(&next) {
+                    if perms.len() == MAX_GROUP_SIZE {
+                        return Symmetries::none();
+                    }
+                    index.insert(next.clone(), perms.len());
+                    perms.push(next.clone());
+                    todo.push(next);
+                }
+            }
+        }
+
+        let inverses = perms.iter().map(|a| index[&invert(a)]).collect();
+
+        Symmetries { perms, inverses, index }
+    }
+
+    pub fn len(&self) -> usize {
+        self.perms.len()
+    }
+
+    pub fn perm(&self, index: usize) -> &[usize] {
+        &self.perms[index]
+    }
+
+    pub fn inverse(&self, index: usize) -> usize {
+        self.inverses[index]
+    }
+
+    /// The index of the permutation that applies `second` after `first`.
+    pub fn then(&self, first: usize, second: usize) -> usize {
+        if self.perms.len() == 1 { return 0; }
+
+        self.index[&compose(&self.perms[second], &self.perms[first])]
+    }
+
+    /// The smallest signal in the orbit of `signal`, equal for all signals that can be mapped onto each other.
+    pub fn class<B: BitSet>(&self, signal: &Signal<B>) -> Signal<B> {
+        self.perms.iter().map(|p| signal.permute_rows(p)).min().unwrap()
+    }
+}
+
+fn is_symmetry<B: BitSet>(query: &Query<B>, perm: &[usize], i: usize, j: usize) -> bool {
+    let inputs_ok = query.inputs.iter().enumerate().all(|(k, s)| {
+        let expected = if k == i { j } else if k == j { i } else { k };
+        s.permute_rows(perm) == query.inputs[expected]
+    });
+
+    inputs_ok &&
+        query.power.iter().all(|s| s.permute_rows(perm) == *s) &&
+        query.outputs.iter().all(|cs| cs.permute_rows(perm) == *cs)
+}
+
+fn invert(perm: &[usize]) -> Vec<usize> {
+    let mut inverse = vec![0; perm.len()];
+    for (i, &p) in perm.iter().enumerate() {
+        inverse[p] = i;
+    }
+    inverse
+}
+
+/// `a` after `b`
+fn compose(a: &[usize], b: &[usize]) -> Vec<usize> {
+    b.iter().map(|&r| a[r]).collect()
+}
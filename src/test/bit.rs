@@ -0,0 +1,68 @@
+use crate::bit::Bit;
+use crate::signal::Signal;
+
+type BinarySignalOp = fn(&Signal<u64>, &Signal<u64>) -> Option<Signal<u64>>;
+
+/// Every combination of two values, as two signals with one row per combination.
+fn all_pairs() -> (Vec<Bit>, Vec<Bit>) {
+    Bit::ALL.iter().flat_map(|&a| Bit::ALL.iter().map(move |&b| (a, b))).unzip()
+}
+
+/// Check `signal_op` against `bit_op` for every pair of values on its own, and for all pairs at once where
+/// `bit_op` succeeds. A single failing row makes the whole signal operation fail.
+fn check_binary(name: &str, signal_op: BinarySignalOp, bit_op: fn(Bit, Bit) -> Option<Bit>) {
+    let (a, b) = all_pairs();
+
+    for (&a, &b) in a.iter().zip(&b) {
+        let actual = signal_op(&Signal::from_rows(&[a]), &Signal::from_rows(&[b])).map(|s| s.get_row(0));
+        assert_eq!(actual, bit_op(a, b), "{}({:?}, {:?})", name, a, b);
+    }
+
+    let (a, b): (Vec<Bit>, Vec<Bit>) = a.iter().zip(&b).filter(|(&a, &b)| bit_op(a, b).is_some()).unzip();
+    let expected: Vec<Bit> = a.iter().zip(&b).map(|(&a, &b)| bit_op(a, b).unwrap()).collect();
+    let actual = signal_op(&Signal::from_rows(&a), &Signal::from_rows(&b));
+    assert_eq!(actual, Some(Signal::from_rows(&expected)), "{} on all rows", name);
+}
+
+#[test]
+fn test_rows() {
+    let signal = Signal::<u8>::from_str("X↑↓Z10");
+    assert_eq!((0..6).map(|i| signal.get_row(i)).collect::<Vec<_>>(), vec![
+        Bit::S0, Bit::S1, Bit::Z, Bit::W0, Bit::W1, Bit::X,
+    ]);
+    assert_eq!(Signal::from_rows(&[Bit::S0, Bit::S1, Bit::Z, Bit::W0, Bit::W1, Bit::X]), signal);
+    assert_eq!(Signal::<u8>::from_rows(&[]), Signal::floating(0));
+
+    for &bit in &Bit::ALL {
+        assert_eq!(Bit::from_levels(bit.levels()), Some(bit));
+    }
+    assert_eq!(Bit::from_levels((true, true, false)), None);
+}
+
+#[test]
+fn test_connect() {
+    check_binary("connect", Signal::connect, Bit::connect);
+    check_binary("connect_ratioed", Signal::connect_ratioed, Bit::connect_ratioed);
+    check_binary("connect_contention", Signal::connect_contention, |a, b| Some(Bit::resolve(a, b)));
+    check_binary("resolve", |a, b| Some(Signal::resolve(a, b)), |a, b| Some(Bit::resolve(a, b)));
+}
+
+#[test]
+fn test_devices() {
+    check_binary("pmos", Signal::pmos, Bit::pmos);
+    check_binary("nmos", Signal::nmos, Bit::nmos);
+    check_binary("transmission_gate", Signal::transmission_gate, Bit::transmission_gate);
+
+    check_binary("pmos_weak_gate", |g, d| Some(Signal::pmos_weak_gate(g, d)), |g, d| Some(Bit::pmos_weak_gate(g, d)));
+    check_binary("nmos_weak_gate", |g, d| Some(Signal::nmos_weak_gate(g, d)), |g, d| Some(Bit::nmos_weak_gate(g, d)));
+    check_binary(
+        "transmission_gate_weak_gate",
+        |g, d| Some(Signal::transmission_gate_weak_gate(g, d)),
+        |g, d| Some(Bit::transmission_gate_weak_gate(g, d)),
+    );
+
+    let signal = Signal::<u8>::from_rows(&Bit::ALL);
+    let map = |f: fn(Bit) -> Bit| Signal::from_rows(&Bit::ALL.map(f));
+    assert_eq!(Signal::resistor(&signal), map(Bit::resistor));
+    assert_eq!(signal.complement(), map(Bit::complement));
+}
@@ -0,0 +1,71 @@
+use crate::bitset::{ArrayBitSet, DynBitSet};
+use crate::pathfind::{solve, SolveOptions};
+use crate::signal::{BitSet, CareSignal, Query, Signal};
+
+fn check_bit_ops<B: BitSet>() {
+    let mut a = B::zero();
+    a.set(3, true);
+    a.set(100, true);
+    assert!(a.get(3) && a.get(100) && !a.get(4) && !a.get(99));
+
+    let mut b = !B::zero();
+    b.set(100, false);
+    assert!(!b.get(100) && b.get(101) && b.get(127));
+
+    assert_eq!(a.clone() & b.clone(), {
+        let mut c = B::zero();
+        c.set(3, true);
+        c
+    });
+    assert!((a.clone() | b.clone()).all_ones());
+    assert_eq!(a.clone() ^ a.clone(), B::zero());
+    assert_eq!(!!a.clone(), a);
+
+    //clearing the bits again gives the same value as never setting them
+    a.set(3, false);
+    a.set(100, false);
+    assert_eq!(a, B::zero());
+}
+
+#[test]
+fn test_bit_ops() {
+    check_bit_ops::<u128>();
+    check_bit_ops::<ArrayBitSet<2>>();
+    check_bit_ops::<DynBitSet>();
+}
+
+/// Row `r` of input `i` is bit `i` of `r`, like the queries in `main`.
+fn input_string(input: usize, inputs: usize) -> String {
+    (0..1 << inputs).rev().map(|r| if (r >> input) & 1 == 1 { '1' } else { '0' }).collect()
+}
+
+fn check_wide_query<B: BitSet>() {
+    let rows = 1 << 8;
+    let power = [Signal::from_str(&"1".repeat(rows)), Signal::from_str(&"0".repeat(rows))];
+    let input_strings: Vec<String> = (0..8).map(|i| input_string(i, 8)).collect();
+    let inputs: Vec<Signal<B>> = input_strings.iter().map(|s| Signal::from_str(s)).collect();
+
+    //the output only depends on the last input, the other inputs are not interchangeable with it
+    let output_string: String = input_string(7, 8).chars().map(|c| if c == '0' { '1' } else { '0' }).collect();
+    let output: Signal<B> = Signal::from_str(&output_string);
+    let query = Query {
+        power: &power,
+        inputs: &inputs,
+        outputs: &[CareSignal::new(output.clone(), output.row_mask())],
+    };
+
+    assert_eq!(query.check(), Ok(()));
+    assert_eq!(format!("{:?}", inputs[0]), format!("[{}]", input_strings[0]));
+
+    //canonicalizing every position under the 5040 permutations of the other inputs is slow in debug builds
+    let options = SolveOptions { max_devices: 2, symmetry_breaking: false, ..Default::default() };
+    let solution = solve(&query, &options).unwrap().solution.unwrap();
+    assert_eq!(solution.device_count(), 2);
+    assert_eq!(solution.nets[solution.outputs[0]].signal, output);
+}
+
+#[test]
+fn test_wide_query() {
+    check_wide_query::<ArrayBitSet<4>>();
+    check_wide_query::<DynBitSet>();
+}
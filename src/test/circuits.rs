@@ -1,5 +1,28 @@
-use crate::pathfind::main_pathfind;
-use crate::signal::{CareSignal, Query, Signal};
+use crate::pathfind::{main_pathfind, solve, SolveOptions, Strategy};
+use crate::signal::{BitSet, CareSignal, MatchMode, Query, Signal};
+use crate::solution::{CostModel, NetOrigin};
+use crate::test::pseudo_nmos_nand2_query;
+
+/// Solve with every strategy, check that they agree on the device count and return it.
+fn device_count<B: BitSet>(query: &Query<B>, max_devices: usize) -> Option<usize> {
+    device_count_with(query, &SolveOptions { max_devices, ..Default::default() })
+}
+
+/// Like `device_count`, with the strategy and symmetry breaking of `options` replaced.
+fn device_count_with<B: BitSet>(query: &Query<B>, options: &SolveOptions) -> Option<usize> {
+    let bfs = SolveOptions { strategy: Strategy::Bfs, symmetry_breaking: false, ..*options };
+    let expected = solve(query, &bfs).unwrap().solution.map(|s| s.device_count());
+
+    for &strategy in &[Strategy::Bfs, Strategy::IterativeDeepening, Strategy::UniformCost, Strategy::AStar] {
+        for &symmetry_breaking in &[false, true] {
+            let options = SolveOptions { strategy, symmetry_breaking, ..*options };
+            let actual = solve(query, &options).unwrap().solution.map(|s| s.device_count());
+            assert_eq!(expected, actual, "strategy {:?} with {:?} disagrees", strategy, options);
+        }
+    }
+
+    expected
+}
 
 #[test]
 fn test_single_mos() {
@@ -9,7 +32,7 @@ fn test_single_mos() {
         outputs: &[CareSignal::new(Signal::from_str("1Z"), 0b11)],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(1));
+    assert_eq!(device_count(&query, 8), Some(1));
 
     let query = Query::<u8> {
         power: &[Signal::from_str("11"), Signal::from_str("00")],
@@ -17,7 +40,7 @@ fn test_single_mos() {
         outputs: &[CareSignal::new(Signal::from_str("Z0"), 0b11)],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(1));
+    assert_eq!(device_count(&query, 8), Some(1));
 }
 
 #[test]
@@ -28,7 +51,174 @@ fn test_not() {
         outputs: &[CareSignal::new(Signal::from_str("10"), 0b11)],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(2));
+    assert_eq!(device_count(&query, 8), Some(2));
+}
+
+#[test]
+fn test_not_netlist() {
+    let query = Query::<u8> {
+        power: &[Signal::from_str("11"), Signal::from_str("00")],
+        inputs: &[Signal::from_str("01")],
+        outputs: &[CareSignal::new(Signal::from_str("10"), 0b11)],
+    };
+
+    let solution = main_pathfind(&query, 8).unwrap();
+    let output = solution.outputs[0];
+
+    assert_eq!(solution.net_name(output), "y");
+    assert_eq!(solution.nets[output].origin, NetOrigin::Internal);
+    assert!(solution.nets[output].is_merged());
+
+    let mut kinds: Vec<_> = solution.devices.iter().map(|d| {
+        assert_eq!(d.gate.unwrap(), solution.nets.iter().position(|n| n.origin == NetOrigin::Input(0)).unwrap());
+        assert_eq!(d.drain, output);
+        (format!("{:?}", d.kind), solution.net_name(d.source))
+    }).collect();
+    kinds.sort();
+    assert_eq!(kinds, vec![("NMOS".to_string(), "gnd"), ("PMOS".to_string(), "vdd")]);
+}
+
+#[test]
+fn test_solve_report() {
+    let query = Query::<u8> {
+        power: &[Signal::from_str("11"), Signal::from_str("00")],
+        inputs: &[Signal::from_str("01")],
+        outputs: &[CareSignal::new(Signal::from_str("01"), 0b11)],
+    };
+
+    //multiple solves running concurrently must not interfere with each other
+    let reports: Vec<_> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| s.spawn(|| solve(&query, &SolveOptions::default()).unwrap()))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    for report in reports {
+        assert_eq!(report.solution.map(|s| s.device_count()), Some(4));
+        assert_eq!(report.stats.expanded_per_depth.len(), 4);
+        assert_eq!(report.stats.expanded_per_depth[0], 1);
+        assert!(report.stats.branching_factor() > 1.0);
+    }
+}
+
+#[test]
+fn test_bfs_threads() {
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[
+            Signal::from_str("0011"),
+            Signal::from_str("0101")
+        ],
+        outputs: &[CareSignal::new(
+            Signal::from_str("1000"),
+            0b1111,
+        )],
+    };
+
+    //the exact same netlist has to come out no matter how the work is split
+    let solutions: Vec<String> = [1, 2, 3, 8].iter().map(|&threads| {
+        let options = SolveOptions { threads, ..Default::default() };
+        solve(&query, &options).unwrap().solution.unwrap().to_string()
+    }).collect();
+
+    for solution in &solutions {
+        assert_eq!(solution, &solutions[0]);
+    }
+}
+
+/// Collect all minimal solutions with every strategy that supports it and check that they agree on the number.
+fn solution_count<B: BitSet>(query: &Query<B>, max_solutions: usize) -> usize {
+    let mut counts = vec![];
+    for &strategy in &[Strategy::Bfs, Strategy::IterativeDeepening] {
+        for &symmetry_breaking in &[false, true] {
+            let options = SolveOptions { strategy, symmetry_breaking, max_solutions, ..Default::default() };
+            let report = solve(query, &options).unwrap();
+
+            let expected = report.solution.as_ref().map(|s| s.device_count());
+            assert!(report.solutions.iter().all(|s| Some(s.device_count()) == expected));
+            counts.push(report.solutions.len());
+        }
+    }
+
+    assert!(counts.iter().all(|&c| c == counts[0]), "strategies disagree: {:?}", counts);
+    counts[0]
+}
+
+#[test]
+fn test_all_solutions() {
+    let inputs = [Signal::from_str("0011"), Signal::from_str("0101")];
+    let outputs = [CareSignal::new(Signal::from_str("1000"), 0b1111)];
+
+    //both orders of the pmos stack are found, but they're the same up to swapping the inputs
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &inputs,
+        outputs: &outputs,
+    };
+    assert_eq!(solution_count(&query, 10), 1);
+
+    //the extra power signal makes the inputs distinguishable
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000"), Signal::from_str("0011")],
+        inputs: &inputs,
+        outputs: &outputs,
+    };
+    assert_eq!(solution_count(&query, 10), 2);
+    assert_eq!(solution_count(&query, 1), 1);
+}
+
+#[test]
+fn test_cost_model() {
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011"), Signal::from_str("0101"), Signal::from_str("1100")],
+        outputs: &[CareSignal::new(Signal::from_str("1111"), 0b1111)],
+    };
+
+    let fewest = main_pathfind(&query, 4).unwrap();
+    assert_eq!(fewest.device_count(), 2);
+    assert_eq!(fewest.cost(&CostModel::default()), 2);
+
+    //two parallel pmos are more expensive than a pmos driven by two parallel nmos
+    let cost = CostModel { pmos: 10, nmos: 1, resistor: 1, series: 0, net: 0 };
+    assert_eq!(fewest.cost(&cost), 20);
+
+    for &strategy in &[Strategy::UniformCost, Strategy::AStar] {
+        let options = SolveOptions { max_devices: 4, strategy, cost, ..Default::default() };
+        let cheapest = solve(&query, &options).unwrap().solution.unwrap();
+        assert_eq!(cheapest.device_count(), 3);
+        assert_eq!(cheapest.cost(&cost), 12);
+    }
+}
+
+#[test]
+fn test_max_series() {
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[
+            Signal::from_str("0011"),
+            Signal::from_str("0101")
+        ],
+        outputs: &[CareSignal::new(
+            Signal::from_str("1110"),
+            0b1111,
+        )],
+    };
+
+    for &strategy in &[Strategy::Bfs, Strategy::IterativeDeepening, Strategy::UniformCost, Strategy::AStar] {
+        let solve_series = |max_series| {
+            let options = SolveOptions { max_devices: 4, strategy, max_series, ..Default::default() };
+            solve(&query, &options).unwrap().solution
+        };
+
+        //the nmos stack of a nand needs two devices in series
+        let solution = solve_series(Some(2)).unwrap();
+        assert_eq!(solution.device_count(), 4);
+        assert_eq!(solution.max_series(), 2);
+
+        assert!(solve_series(Some(1)).is_none(), "strategy {:?} ignores max_series", strategy);
+    }
 }
 
 #[test]
@@ -39,7 +229,7 @@ fn test_buffer() {
         outputs: &[CareSignal::new(Signal::from_str("01"), 0b11)],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(4));
+    assert_eq!(device_count(&query, 8), Some(4));
 }
 
 #[test]
@@ -50,7 +240,7 @@ fn test_buffer_inv_given() {
         outputs: &[CareSignal::new(Signal::from_str("01"), 0b11)],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(2));
+    assert_eq!(device_count(&query, 8), Some(2));
 }
 
 #[test]
@@ -67,7 +257,125 @@ fn test_nand2() {
         )],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(4));
+    assert_eq!(device_count(&query, 8), Some(4));
+}
+
+#[test]
+fn test_dont_care() {
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011"), Signal::from_str("0101")],
+        outputs: &[CareSignal::from_str("1--Z")],
+    };
+    assert_eq!(device_count(&query, 8), Some(1));
+
+    //the middle rows have to be driven now, so both pmos of a nand are needed
+    let query = Query::<u8> {
+        outputs: &[CareSignal::from_str("1??Z")],
+        ..query
+    };
+    assert_eq!(device_count(&query, 8), Some(2));
+}
+
+#[test]
+fn test_match_mode() {
+    //with only vdd the high level can only come from a pmos that's on when the input is low,
+    //or from an nmos that passes a weak high
+    let query = Query::<u8> {
+        power: &[Signal::from_str("11")],
+        inputs: &[Signal::from_str("01")],
+        outputs: &[CareSignal::from_str("Z1")],
+    };
+    assert_eq!(device_count(&query, 4), None);
+
+    let outputs = [CareSignal::from_str("Z1").with_mode(MatchMode::Level)];
+    assert_eq!(device_count(&Query { outputs: &outputs, ..query }, 4), Some(1));
+
+    let outputs = [CareSignal::from_str("Z↑").with_mode(MatchMode::Strong)];
+    assert_eq!(device_count(&Query { outputs: &outputs, ..query }, 4), None);
+
+    let outputs = [CareSignal::from_str("↑Z").with_mode(MatchMode::Strong)];
+    assert_eq!(device_count(&Query { outputs: &outputs, ..query }, 4), Some(1));
+}
+
+#[test]
+fn test_transmission_gate_mux() {
+    //y = s ? a : b with the data inputs as power, so they can be passed through
+    let query = Query::<u8> {
+        power: &[Signal::from_str("0011_0011"), Signal::from_str("0101_0101")],
+        inputs: &[Signal::from_str("0000_1111"), Signal::from_str("1111_0000")],
+        outputs: &[CareSignal::from_str("0101_0011")],
+    };
+
+    assert_eq!(device_count(&query, 4), Some(4));
+
+    for &strategy in &[Strategy::Bfs, Strategy::IterativeDeepening] {
+        let options = SolveOptions { max_devices: 4, strategy, transmission_gates: true, ..Default::default() };
+        let solution = solve(&query, &options).unwrap().solution.unwrap();
+
+        assert_eq!(solution.device_count(), 4, "strategy {:?}", strategy);
+        assert_eq!(solution.transistors().len(), 4);
+
+        //a transmission gate counts as its two transistors, so there is no smaller mux
+        let options = SolveOptions { max_devices: 3, ..options };
+        assert!(solve(&query, &options).unwrap().solution.is_none(), "strategy {:?}", strategy);
+    }
+
+    //a transmission gate costs as much as the two separate transistors
+    for &strategy in &[Strategy::UniformCost, Strategy::AStar] {
+        let options = SolveOptions { max_devices: 4, strategy, transmission_gates: true, ..Default::default() };
+        let solution = solve(&query, &options).unwrap().solution.unwrap();
+        assert_eq!(solution.cost(&CostModel::default()), 4, "strategy {:?}", strategy);
+    }
+}
+
+#[test]
+fn test_pseudo_nmos_nand2() {
+    let query = pseudo_nmos_nand2_query();
+    assert_eq!(device_count(&query, 4), Some(4));
+
+    //a weak pull-up replaces the pmos pair and the nmos stack overrides it in the last row, or the other way around
+    let ratioed = [
+        SolveOptions { resistors: true, ..Default::default() },
+        SolveOptions { pull_devices: true, ..Default::default() },
+    ];
+    for options in &ratioed {
+        for &strategy in &[Strategy::Bfs, Strategy::IterativeDeepening, Strategy::UniformCost, Strategy::AStar] {
+            let options = SolveOptions { max_devices: 4, strategy, ..*options };
+            let solution = solve(&query, &options).unwrap().solution.unwrap();
+            assert_eq!(solution.device_count(), 3, "{:?}", options);
+
+            let output = solution.outputs[0];
+            let expected = if solution.nets[output].signal == Signal::from_str("↑↑↑0") { 0b0001 } else { 0b1110 };
+            assert_eq!(solution.static_current(), vec![(output, expected)]);
+        }
+    }
+}
+
+#[test]
+fn test_contention() {
+    let outputs = [CareSignal::from_str("1--0")];
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011"), Signal::from_str("0101")],
+        outputs: &outputs,
+    };
+
+    let options = SolveOptions { max_devices: 2, max_solutions: 10, allow_contention: true, ..Default::default() };
+    let solutions = solve(&query, &options).unwrap().solutions;
+
+    //a pmos pulling up when a is low and an nmos pulling down when b is high fight in a don't care row
+    let shorted: Vec<_> = solutions.iter().filter(|s| !s.contention().is_empty()).collect();
+    assert!(!shorted.is_empty());
+    for solution in shorted {
+        let output = solution.outputs[0];
+        assert_eq!(solution.contention(), vec![(output, 0b0100)]);
+        assert!(solution.to_string().contains("contention y in rows [2]"));
+    }
+
+    let options = SolveOptions { allow_contention: false, ..options };
+    let solutions = solve(&query, &options).unwrap().solutions;
+    assert!(solutions.iter().all(|s| s.contention().is_empty()));
 }
 
 #[test]
@@ -84,7 +392,7 @@ fn test_nor2() {
         )],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(4));
+    assert_eq!(device_count(&query, 8), Some(4));
 }
 
 #[test]
@@ -102,7 +410,7 @@ fn test_and2() {
         )],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(6));
+    assert_eq!(device_count(&query, 8), Some(6));
 }
 
 #[test]
@@ -120,7 +428,7 @@ fn test_tristate_buffer() {
         )],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(6));
+    assert_eq!(device_count(&query, 8), Some(6));
 }
 
 #[test]
@@ -139,11 +447,11 @@ fn test_nand3() {
         )],
     };
 
-    assert_eq!(main_pathfind(&query, 8), Some(6));
+    assert_eq!(device_count(&query, 8), Some(6));
 }
 
-//too slow, never finishes at all
-/*#[test]
+//bfs never finishes at all, only a* is fast enough
+#[test]
 #[ignore]
 fn test_xor2() {
     let query = Query::<u8> {
@@ -158,5 +466,43 @@ fn test_xor2() {
         )],
     };
 
-    assert_eq!(main_pathfind(&query, 10), Some(6));
-}*/
\ No newline at end of file
+    let options = SolveOptions { max_devices: 10, strategy: Strategy::AStar, ..Default::default() };
+    let solution = solve(&query, &options).unwrap().solution;
+    assert_eq!(solution.map(|s| s.device_count()), Some(9));
+}
+
+#[test]
+fn test_weak_gates() {
+    let outputs = [CareSignal::from_str("--11")];
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011"), Signal::from_str("0101")],
+        outputs: &outputs,
+    };
+    assert_eq!(device_count(&query, 4), Some(3));
+
+    //the pull-down of an inverter is enough to drive a pmos gate, which gives X in the rows where the gate floats
+    for &strategy in &[Strategy::Bfs, Strategy::IterativeDeepening, Strategy::UniformCost, Strategy::AStar] {
+        for &symmetry_breaking in &[false, true] {
+            let options = SolveOptions {
+                max_devices: 4, strategy, symmetry_breaking, weak_gates: true, ..Default::default()
+            };
+            let solution = solve(&query, &options).unwrap().solution.unwrap();
+            assert_eq!(solution.device_count(), 2, "{:?}", options);
+
+            let output = solution.outputs[0];
+            assert_eq!(solution.nets[output].signal, Signal::from_str("XX11"));
+            assert_eq!(solution.contention(), vec![(output, 0b1100)]);
+        }
+    }
+
+    //a contention row only needs the one transistor with a floating gate, the lower bound can't count it as two
+    let outputs = [CareSignal::from_str("1Z"), CareSignal::new(Signal::from_str("ZX"), 0b11)];
+    let query = Query::<u8> {
+        power: &[Signal::from_str("11"), Signal::from_str("00")],
+        inputs: &[Signal::from_str("01")],
+        outputs: &outputs,
+    };
+    let options = SolveOptions { max_devices: 2, weak_gates: true, ..Default::default() };
+    assert_eq!(device_count_with(&query, &options), Some(2));
+}
@@ -0,0 +1,392 @@
+use crate::export::circuitikz::to_circuitikz;
+use crate::export::dot::to_dot;
+use crate::export::falstad::to_falstad;
+use crate::export::spice::{to_spice, MosModel, SpiceOptions, Testbench};
+use crate::export::svg::to_svg;
+use crate::export::verilog::{behavioural_model, to_verilog};
+use crate::export::ExportError;
+use crate::pathfind::{solve, SolveOptions};
+use crate::signal::{CareSignal, MatchMode, Query, Signal};
+use crate::solution::{Kind, Solution, Step};
+use crate::test::{leak, pseudo_nmos_nand2_query};
+
+fn inverter() -> (Query<'static, u8>, Solution<u8>) {
+    let query = Query::<u8> {
+        power: leak(vec![Signal::from_str("11"), Signal::from_str("00")]),
+        inputs: leak(vec![Signal::from_str("01")]),
+        outputs: leak(vec![CareSignal::from_str("10")]),
+    };
+    let step = |kind, power: &str, merge: Option<&str>, output: &str| Step {
+        kind,
+        power: Signal::from_str(power),
+        gate: Some(Signal::from_str("01")),
+        merge: merge.map(Signal::from_str),
+        output: Signal::from_str(output),
+    };
+    let solution = Solution::from_steps(
+        &query, &[step(Kind::PMOS, "11", None, "1Z"), step(Kind::NMOS, "00", Some("1Z"), "10")],
+    );
+    (query, solution)
+}
+
+/// A 2:1 mux from two transmission gates. The data inputs are the power signals, so there is no vdd or gnd, and
+/// the select inputs are complementary. Row 2 is don't care.
+fn mux() -> (Query<'static, u8>, Solution<u8>) {
+    let query = Query::<u8> {
+        power: leak(vec![Signal::from_str("0011_0011"), Signal::from_str("0101_0101")]),
+        inputs: leak(vec![Signal::from_str("0000_1111"), Signal::from_str("1111_0000")]),
+        outputs: leak(vec![CareSignal::from_str("0101_0-11")]),
+    };
+    let step = |power: &str, gate: &str, merge: Option<&str>, output: &str| Step {
+        kind: Kind::TransmissionGate,
+        power: Signal::from_str(power),
+        gate: Some(Signal::from_str(gate)),
+        merge: merge.map(Signal::from_str),
+        output: Signal::from_str(output),
+    };
+    let solution = Solution::from_steps(&query, &[
+        step("0011_0011", "0000_1111", None, "ZZZZ_0011"),
+        step("0101_0101", "1111_0000", Some("ZZZZ_0011"), "0101_0011"),
+    ]);
+    (query, solution)
+}
+
+/// The nand2 of `circuits::test_pseudo_nmos_nand2` with 3 devices, `options` allows pull devices or resistors.
+fn pseudo_nmos_nand2(options: SolveOptions) -> (Query<'static, u8>, Solution<u8>) {
+    let query = pseudo_nmos_nand2_query();
+    let solution = solve(&query, &SolveOptions { max_devices: 3, ..options }).unwrap().solution.unwrap();
+    (query, solution)
+}
+
+#[test]
+fn test_spice_inverter() {
+    let options = SpiceOptions { name: "inv".to_string(), testbench: Some(Testbench::default()), ..Default::default() };
+    let expected = "\
+* inv: 2 devices
+.subckt inv a y vdd gnd
+M1 y a vdd vdd pmos W=2u L=180n
+M2 y a gnd gnd nmos W=1u L=180n
+.ends inv
+
+* testbench, row i of the inputs is applied from i * 10ns
+Xinv a y vdd gnd inv
+Vvdd vdd 0 1.8
+Vgnd gnd 0 0
+Va a 0 PWL(0n 1.8 10n 1.8 10.1n 0)
+* expected y = [10], the last row first
+.tran 0.1n 20n
+.end
+";
+    assert_eq!(to_spice(&inverter().1, &options), Ok(expected.to_string()));
+}
+
+#[test]
+fn test_spice_models() {
+    //without vdd or gnd the bulk of the pmos is its source and the bulk of the nmos is the global ground
+    let (_, solution) = mux();
+    let options = SpiceOptions { transmission_gate_pmos: MosModel::new("p_tg", "4u", "1u"), ..Default::default() };
+    let spice = to_spice(&solution, &options).unwrap();
+    assert!(spice.contains(".subckt cell a b y pwr0 pwr1\n"), "{}", spice);
+
+    let transistors: Vec<Vec<&str>> = spice.lines()
+        .filter(|l| l.starts_with('M'))
+        .map(|l| l.split(' ').collect())
+        .collect();
+    assert_eq!(transistors.len(), 4);
+    for t in &transistors {
+        let (source, bulk) = (t[3], t[4]);
+        match t[5] {
+            "p_tg" => assert_eq!((bulk, &t[6..]), (source, &["W=4u", "L=1u"][..])),
+            _ => assert_eq!((bulk, &t[5..]), ("0", &["nmos", "W=1u", "L=180n"][..])),
+        }
+    }
+}
+
+#[test]
+fn test_spice_pull_devices() {
+    let (_, solution) = pseudo_nmos_nand2(SolveOptions { pull_devices: true, ..Default::default() });
+    let spice = to_spice(&solution, &Default::default()).unwrap();
+    assert!(
+        spice.contains("y gnd vdd vdd pmos W=500n L=2u") || spice.contains("y vdd gnd gnd nmos W=500n L=2u"),
+        "{}", spice
+    );
+
+    let (_, solution) = pseudo_nmos_nand2(SolveOptions { resistors: true, ..Default::default() });
+    let spice = to_spice(&solution, &Default::default()).unwrap();
+    assert!(spice.contains("R1 y vdd 100k") || spice.contains("R1 y gnd 100k"), "{}", spice);
+
+    //a pull-down without a power signal that is high everywhere has nothing to tie its gate to
+    let query = Query::<u8> {
+        power: &[Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011")],
+        outputs: &[CareSignal::from_str("↓↓↓↓")],
+    };
+    let options = SolveOptions { max_devices: 1, pull_devices: true, ..Default::default() };
+    let solution = solve(&query, &options).unwrap().solution.unwrap();
+    assert_eq!(to_spice(&solution, &Default::default()), Err(ExportError::MissingSupply { high: true }));
+}
+
+#[test]
+fn test_verilog_inverter() {
+    let expected = "\
+// inv: 2 devices
+module inv(a, y);
+    input a;
+    output y;
+    supply1 vdd;
+    supply0 gnd;
+    wire y;
+
+    pmos m1(y, vdd, a);
+    nmos m2(y, gnd, a);
+endmodule
+";
+    assert_eq!(to_verilog(&inverter().1, "inv"), expected);
+}
+
+#[test]
+fn test_verilog_mux() {
+    let (query, solution) = mux();
+
+    //the power signals are data inputs, the complementary select inputs only drive transmission gates
+    let verilog = to_verilog(&solution, "mux");
+    assert!(verilog.starts_with("// mux: 4 devices\nmodule mux(a, b, y, pwr0, pwr1);\n"), "{}", verilog);
+    assert!(verilog.contains("    input a, b, pwr0, pwr1;\n    output y;\n    wire y;\n"), "{}", verilog);
+    assert_eq!(verilog.matches("    tranif1 m").count(), 2, "{}", verilog);
+
+    let model = behavioural_model(&solution, &query, "mux_model");
+    let expected = "\
+// mux_model: 4 devices
+module mux_model(a, b, y, pwr0, pwr1);
+    input a, b, pwr0, pwr1;
+    output y;
+    reg y;
+
+    always @(*) begin
+        case ({a, b, pwr0, pwr1})
+            4'b1011: y = 1'b1;
+            4'b1010: y = 1'b1;
+            4'b1001: y = 1'bx;
+            4'b1000: y = 1'b0;
+            4'b0111: y = 1'b1;
+            4'b0110: y = 1'b0;
+            4'b0101: y = 1'b1;
+            4'b0100: y = 1'b0;
+            default: y = 1'bx;
+        endcase
+    end
+endmodule
+";
+    assert_eq!(model, expected);
+}
+
+#[test]
+fn test_verilog_tristate() {
+    //y is only driven when a is low
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011"), Signal::from_str("0101")],
+        outputs: &[CareSignal::from_str("11ZZ")],
+    };
+    let solution = solve(&query, &SolveOptions { max_devices: 1, ..Default::default() }).unwrap().solution.unwrap();
+    let verilog = to_verilog(&solution, "tri_pmos");
+    assert!(verilog.contains("    tri y;\n"), "{}", verilog);
+
+    let (_, solution) = pseudo_nmos_nand2(SolveOptions { pull_devices: true, ..Default::default() });
+    let verilog = to_verilog(&solution, "nand2");
+    assert!(verilog.contains("rpmos m") || verilog.contains("rnmos m"), "{}", verilog);
+}
+
+#[test]
+fn test_dot_inverter() {
+    let expected = "\
+digraph \"inv\" {
+    node [fontname=\"Helvetica\"];
+    \"vdd\" [shape=plaintext];
+    \"gnd\" [shape=plaintext];
+    \"a\" [shape=plaintext];
+    \"y\" [shape=box, style=rounded];
+    {rank=source; \"vdd\";}
+    {rank=sink; \"gnd\";}
+    m1 [label=\"M1\\nPMOS\", shape=box, style=filled, fillcolor=\"#f8cecc\"];
+    m2 [label=\"M2\\nNMOS\", shape=box, style=filled, fillcolor=\"#dae8fc\"];
+    \"vdd\" -> m1;
+    \"a\" -> m1 [style=dashed, arrowhead=odot];
+    \"gnd\" -> m2;
+    \"a\" -> m2 [style=dashed];
+    m1 -> \"y\";
+    m2 -> \"y\";
+}
+";
+    assert_eq!(to_dot(&inverter().1, "inv"), expected);
+}
+
+#[test]
+fn test_dot_internal_nets() {
+    let query = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011"), Signal::from_str("0101")],
+        outputs: &[CareSignal::from_str("1110")],
+    };
+    let solution = solve(&query, &SolveOptions { max_devices: 4, ..Default::default() }).unwrap().solution.unwrap();
+
+    //the node between the two nmos in series is an edge between them
+    let dot = to_dot(&solution, "nand2");
+    let series: Vec<&str> = dot.lines().filter(|l| l.contains("[label=\"n1\"]")).collect();
+    assert_eq!(series.len(), 1, "{}", dot);
+    assert!(series[0].trim_start().starts_with('m'), "{}", dot);
+    assert_eq!(dot.matches("arrowhead=odot").count(), 2, "{}", dot);
+}
+
+/// The `y` attribute of the elements of `svg` that contain `pattern`.
+fn svg_y(svg: &str, pattern: &str) -> Vec<i32> {
+    svg.lines()
+        .filter(|l| l.contains(pattern))
+        .map(|l| {
+            let start = l.find(" y=\"").or_else(|| l.find(" y1=\"")).unwrap();
+            let value = &l[start..].split('"').nth(1).unwrap();
+            value.parse().unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn test_svg_inverter() {
+    let svg = to_svg(&inverter().1, "inv");
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""), "{}", svg);
+    assert!(svg.ends_with("</svg>\n"), "{}", svg);
+    assert!(svg.contains("<title>inv</title>"), "{}", svg);
+
+    //vdd at the top, then the pmos, the output, the nmos and gnd at the bottom
+    let label_y = |name: &str| svg_y(&svg, &format!("text-anchor=\"end\">{}</text>", name))[0];
+    let pmos = svg_y(&svg, "fill=\"#f8cecc\"")[0];
+    let nmos = svg_y(&svg, "fill=\"#dae8fc\"")[0];
+    assert!(label_y("vdd") < pmos && pmos < label_y("y") && label_y("y") < nmos && nmos < label_y("gnd"), "{}", svg);
+    assert_eq!(svg.matches("text-anchor=\"end\">a</text>").count(), 2, "{}", svg);
+}
+
+#[test]
+fn test_svg_pull_devices() {
+    let (_, solution) = pseudo_nmos_nand2(SolveOptions { pull_devices: true, ..Default::default() });
+    let svg = to_svg(&solution, "nand2 & <pseudo>");
+    assert!(svg.contains("<title>nand2 &amp; &lt;pseudo&gt;</title>"), "{}", svg);
+    assert_eq!(svg.matches("<rect x=").count(), 3, "{}", svg);
+    assert!(svg.contains(">PU</text>") || svg.contains(">PD</text>"), "{}", svg);
+}
+
+/// The elements of `falstad` with the given type, split into their fields.
+fn falstad_elements<'a>(falstad: &'a str, kind: &str) -> Vec<Vec<&'a str>> {
+    falstad.lines().map(|l| l.split(' ').collect::<Vec<_>>()).filter(|e| e[0] == kind).collect()
+}
+
+#[test]
+fn test_falstad_inverter() {
+    let falstad = to_falstad(&inverter().1);
+    assert!(falstad.starts_with("$ 1 "), "{}", falstad);
+
+    let mosfets = falstad_elements(&falstad, "f");
+    let pmos: Vec<&str> = mosfets.iter().map(|f| f[5]).collect();
+    assert_eq!(pmos, ["1", "0"], "{}", falstad);
+    assert!(mosfets.iter().all(|f| f[6..] == ["1.5", "0.02"]), "{}", falstad);
+
+    //a starts high because row 0 is the first row applied
+    let switches = falstad_elements(&falstad, "L");
+    assert_eq!(switches.len(), 1, "{}", falstad);
+    assert_eq!(switches[0][6], "1", "{}", falstad);
+    assert_eq!((falstad_elements(&falstad, "R").len(), falstad_elements(&falstad, "g").len()), (1, 1), "{}", falstad);
+    assert_eq!(falstad_elements(&falstad, "M").len(), 1, "{}", falstad);
+
+    //every net is joined by labels with its name: the bus and the port, plus the gates for a
+    let labels: Vec<&str> = falstad_elements(&falstad, "207").iter().map(|l| l[6]).collect();
+    assert_eq!(labels.iter().filter(|&&l| l == "a").count(), 3, "{}", falstad);
+    assert_eq!(labels.iter().filter(|&&l| l == "y").count(), 2, "{}", falstad);
+
+    let coordinates = falstad.lines().skip(1).flat_map(|l| l.split(' ').skip(1).take(4));
+    assert!(coordinates.map(|c| c.parse::<i32>().unwrap()).all(|c| c >= 0), "{}", falstad);
+}
+
+#[test]
+fn test_falstad_ports() {
+    //the data inputs of the mux are power nets that change between rows, so they get switches instead of rails
+    let (_, solution) = mux();
+    let falstad = to_falstad(&solution);
+    assert_eq!(falstad_elements(&falstad, "L").len(), 4, "{}", falstad);
+    assert!(falstad_elements(&falstad, "R").is_empty() && falstad_elements(&falstad, "g").is_empty(), "{}", falstad);
+
+    //a transmission gate is a pmos and an nmos on the same channel
+    let mosfets = falstad_elements(&falstad, "f");
+    assert_eq!(mosfets.len(), 4, "{}", falstad);
+    assert_eq!(mosfets.iter().filter(|f| f[5] == "1").count(), 2, "{}", falstad);
+}
+
+#[test]
+fn test_falstad_pull_devices() {
+    let (_, solution) = pseudo_nmos_nand2(SolveOptions { pull_devices: true, ..Default::default() });
+    let falstad = to_falstad(&solution);
+    assert_eq!(falstad_elements(&falstad, "f").iter().filter(|f| f[7] == "0.002").count(), 1, "{}", falstad);
+}
+
+#[test]
+fn test_circuitikz_inverter() {
+    let (query, solution) = inverter();
+    let tikz = to_circuitikz(&solution, &query, "inv");
+    assert!(tikz.starts_with("% inv: 2 devices, needs \\usepackage{circuitikz}\n"), "{}", tikz);
+
+    //both transistors hang from their power net without being flipped
+    assert!(tikz.contains("node[pmos, anchor=S] (m1) {};"), "{}", tikz);
+    assert!(tikz.contains("node[nmos, anchor=S] (m2) {};"), "{}", tikz);
+    assert_eq!(tikz.matches("node[left] {$\\mathit{a}$};").count(), 2, "{}", tikz);
+    assert_eq!(tikz.matches("node[ocirc]").count(), 1, "{}", tikz);
+
+    let table = "\
+\\begin{tabular}{c|c}
+    $\\mathit{a}$ & $\\mathit{y}$ \\\\
+    \\hline
+    0 & 1 \\\\
+    1 & 0 \\\\
+\\end{tabular}
+";
+    assert!(tikz.ends_with(table), "{}", tikz);
+}
+
+#[test]
+fn test_circuitikz_expressions() {
+    //a buffer built from two inverters, the gates of the second one are on the internal net
+    let query = Query::<u8> {
+        power: &[Signal::from_str("11"), Signal::from_str("00")],
+        inputs: &[Signal::from_str("01")],
+        outputs: &[CareSignal::from_str("0-").with_mode(MatchMode::Level)],
+    };
+    let step = |kind, power: &str, gate: &str, merge: Option<&str>, output: &str| Step {
+        kind,
+        power: Signal::from_str(power),
+        gate: Some(Signal::from_str(gate)),
+        merge: merge.map(Signal::from_str),
+        output: Signal::from_str(output),
+    };
+    let solution = Solution::from_steps(&query, &[
+        step(Kind::PMOS, "11", "01", None, "1Z"),
+        step(Kind::NMOS, "00", "01", Some("1Z"), "10"),
+        step(Kind::PMOS, "11", "10", None, "Z1"),
+        step(Kind::NMOS, "00", "10", Some("Z1"), "01"),
+    ]);
+
+    let tikz = to_circuitikz(&solution, &query, "buf");
+    assert_eq!(tikz.matches("node[left] {$\\overline{\\mathit{a}}$};").count(), 2, "{}", tikz);
+    assert!(tikz.contains("    0 & 0 \\\\\n    1 & - \\\\\n"), "{}", tikz);
+}
+
+#[test]
+fn test_circuitikz_mux() {
+    let (query, solution) = mux();
+
+    //the pmos of each transmission gate is mirrored, with its gate on the right
+    let tikz = to_circuitikz(&solution, &query, "mux");
+    assert_eq!(tikz.matches("xscale=-1").count(), 2, "{}", tikz);
+    assert_eq!(tikz.matches("node[right]").count(), 2, "{}", tikz);
+
+    let header = "$\\mathit{a}$ & $\\mathit{b}$ & $\\mathit{pwr0}$ & $\\mathit{pwr1}$ & $\\mathit{y}$ \\\\";
+    assert!(tikz.contains(header), "{}", tikz);
+    assert!(tikz.contains("\\begin{tabular}{cccc|c}"), "{}", tikz);
+    assert_eq!(tikz.lines().filter(|l| l.ends_with("\\\\")).count(), 9, "{}", tikz);
+}
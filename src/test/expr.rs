@@ -0,0 +1,59 @@
+use crate::expr::{ExprError, ExprQuery};
+use crate::pathfind::main_pathfind;
+use crate::signal::{CareSignal, Signal};
+
+#[test]
+fn test_nand3() {
+    let parsed = ExprQuery::<u8>::parse(&["a", "b", "c"], "y = !(a & b & c)").unwrap();
+
+    assert_eq!(parsed.power, vec![Signal::from_str("1111_1111"), Signal::from_str("0000_0000")]);
+    assert_eq!(parsed.inputs, vec![
+        Signal::from_str("0000_1111"),
+        Signal::from_str("0011_0011"),
+        Signal::from_str("0101_0101"),
+    ]);
+    assert_eq!(parsed.outputs, vec![CareSignal::new(Signal::from_str("1111_1110"), 0b1111_1111)]);
+    assert_eq!(parsed.output_names, vec!["y"]);
+    assert_eq!(parsed.query().check(), Ok(()));
+}
+
+#[test]
+fn test_values() {
+    let parse = |s| ExprQuery::<u8>::parse(&["a", "b"], s).unwrap().outputs;
+
+    assert_eq!(parse("y = a ? b : Z"), vec![CareSignal::new(Signal::from_str("ZZ01"), 0b1111)]);
+    assert_eq!(parse("y = a ^ b; z = a | ~b"), vec![
+        CareSignal::new(Signal::from_str("0110"), 0b1111),
+        CareSignal::new(Signal::from_str("1011"), 0b1111),
+    ]);
+
+    //don't care rows are only cared about when the other side decides the result
+    assert_eq!(parse("y = a & X"), vec![CareSignal::new(Signal::from_str("00ZZ"), 0b1100)]);
+    assert_eq!(parse("y = a ? X : 1\n\n"), vec![CareSignal::new(Signal::from_str("11ZZ"), 0b1100)]);
+    assert_eq!(parse("y = !Z"), vec![CareSignal::new(Signal::from_str("ZZZZ"), 0b0000)]);
+}
+
+#[test]
+fn test_solve() {
+    let parsed = ExprQuery::<u8>::parse(&["a", "b"], "y = !(a | b)").unwrap();
+    assert_eq!(main_pathfind(&parsed.query(), 4).unwrap().device_count(), 4);
+}
+
+#[test]
+fn test_errors() {
+    let parse = |inputs: &[&str], s| ExprQuery::<u8>::parse(inputs, s).map(|_| ());
+
+    assert_eq!(parse(&["a", "a"], "y = a"), Err(ExprError::BadName { name: "a".to_string() }));
+    assert_eq!(parse(&["Z"], "y = 1"), Err(ExprError::BadName { name: "Z".to_string() }));
+    assert_eq!(parse(&["a"], "a = 1"), Err(ExprError::BadName { name: "a".to_string() }));
+    assert_eq!(parse(&["a"], ""), Err(ExprError::NoOutputs));
+    assert_eq!(
+        parse(&["a", "b", "c", "d"], "y = a"),
+        Err(ExprError::TooManyInputs { inputs: 4, max_rows: 8 })
+    );
+    assert_eq!(parse(&["a"], "y = b"), Err(ExprError::UnknownName { name: "b".to_string(), position: 4 }));
+    assert_eq!(parse(&["a"], "y = a + 1"), Err(ExprError::BadCharacter { character: '+', position: 6 }));
+    assert_eq!(parse(&["a"], "y = (a"), Err(ExprError::Expected { expected: "')'", position: 6 }));
+    assert_eq!(parse(&["a"], "y = a ? 1"), Err(ExprError::Expected { expected: "':'", position: 9 }));
+    assert_eq!(parse(&["a"], "y = a a"), Err(ExprError::Expected { expected: "end of line", position: 6 }));
+}
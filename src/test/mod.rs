@@ -1 +1,23 @@
-mod circuits;
\ No newline at end of file
+use crate::signal::{CareSignal, MatchMode, Query, Signal};
+
+mod bit;
+mod bitset;
+mod circuits;
+mod export;
+mod expr;
+mod query;
+mod symmetry;
+
+/// Leak `values`, so fixtures can return a `Query` without a separate owner for its signals.
+fn leak<T>(values: Vec<T>) -> &'static [T] {
+    Box::leak(values.into_boxed_slice())
+}
+
+/// A nand2 that only has to match the levels, so a ratioed circuit with a weak pull-up can implement it.
+fn pseudo_nmos_nand2_query() -> Query<'static, u8> {
+    Query {
+        power: leak(vec![Signal::from_str("1111"), Signal::from_str("0000")]),
+        inputs: leak(vec![Signal::from_str("0011"), Signal::from_str("0101")]),
+        outputs: leak(vec![CareSignal::from_str("1110").with_mode(MatchMode::Level)]),
+    }
+}
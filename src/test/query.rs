@@ -0,0 +1,171 @@
+use crate::signal::{CareSignal, MatchMode, Query, QueryError, Signal};
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(
+        Signal::<u8>::parse("01_2Z"),
+        Err(QueryError::BadCharacter { character: '2', position: 3 })
+    );
+    assert_eq!(
+        Signal::<u8>::parse("0101_0101_0"),
+        Err(QueryError::StringTooLong { length: 9, max: 8 })
+    );
+    assert_eq!(Signal::<u8>::parse("0101_0101"), Ok(Signal::from_str("01010101")));
+}
+
+#[test]
+fn test_check_errors() {
+    let valid = Query::<u8> {
+        power: &[Signal::from_str("11"), Signal::from_str("00")],
+        inputs: &[Signal::from_str("01")],
+        outputs: &[CareSignal::new(Signal::from_str("10"), 0b11)],
+    };
+    assert_eq!(valid.check(), Ok(()));
+
+    let query = Query::<u8> { power: &[], ..valid };
+    assert_eq!(query.check(), Err(QueryError::EmptyPower));
+
+    let query = Query::<u8> { inputs: &[Signal::from_str("011")], ..valid };
+    assert_eq!(query.check(), Err(QueryError::MismatchedWidths { expected: 2, found: 3 }));
+
+    let query = Query::<u8> { inputs: &[Signal::from_str("0↑")], ..valid };
+    assert_eq!(query.check(), Err(QueryError::NonStrongInput { input: 0 }));
+
+    let query = Query::<u8> { outputs: &[CareSignal::new(Signal::from_str("10"), 0b111)], ..valid };
+    assert_eq!(query.check(), Err(QueryError::CareOutsideRows { output: 0 }));
+
+    let outputs = [CareSignal { any_strong: 0b100, ..CareSignal::from_str("10") }];
+    let query = Query::<u8> { outputs: &outputs, ..valid };
+    assert_eq!(query.check(), Err(QueryError::CareOutsideRows { output: 0 }));
+}
+
+#[test]
+fn test_rows() {
+    let signal = Signal::<u8>::from_str("01Z");
+    assert_eq!(signal.rows(), 3);
+    assert_eq!(signal.row_mask(), 0b111);
+    assert_eq!(format!("{:?}", signal), "[01Z]");
+
+    //bits past the last row are ignored
+    assert_eq!(Signal::new(2, 0b1110, 0b1101, 0b1111), Signal::<u8>::from_str("01"));
+    assert_eq!(Signal::constant(2, true), Signal::<u8>::from_str("11"));
+    assert_eq!(Signal::floating(2), Signal::<u8>::from_str("ZZ"));
+
+    let gate = Signal::<u8>::from_str("01");
+    assert_eq!(Signal::pmos(&gate, &Signal::from_str("11")), Some(Signal::from_str("1Z")));
+    assert_eq!(Signal::<u8>::connect(&Signal::from_str("Z1"), &Signal::from_str("0Z")), Some(Signal::from_str("01")));
+
+    let care = CareSignal::new(Signal::<u8>::from_str("10"), 0b11);
+    assert!(care.matches(&Signal::from_str("10")));
+    assert!(!care.matches(&Signal::from_str("010")));
+}
+
+#[test]
+#[should_panic(expected = "different amount of rows")]
+fn test_mismatched_rows() {
+    Signal::connect(&Signal::<u8>::from_str("01"), &Signal::from_str("011"));
+}
+
+#[test]
+fn test_care_signal_parse() {
+    let cs = CareSignal::<u8>::from_str("1-?X_HL");
+    assert_eq!(cs.signal, Signal::from_str("1ZZZ↑↓"));
+    assert_eq!(cs.care, 0b100011);
+    assert_eq!(cs.any_strong, 0b001000);
+    assert_eq!(format!("{:?}", cs), "[1-?-↑↓]");
+    assert_eq!(CareSignal::<u8>::from_str("1-?-↑↓"), cs);
+
+    assert!(cs.matches(&Signal::from_str("1Z0Z↑↓")));
+    assert!(cs.matches(&Signal::from_str("101Z↑↓")));
+    assert!(!cs.matches(&Signal::from_str("1Z↓Z↑↓")));
+    assert!(!cs.matches(&Signal::from_str("0Z0Z↑↓")));
+
+    assert_eq!(
+        CareSignal::<u8>::parse("1?*"),
+        Err(QueryError::BadCharacter { character: '*', position: 2 })
+    );
+    assert_eq!(
+        CareSignal::<u8>::parse("----_----_-"),
+        Err(QueryError::StringTooLong { length: 9, max: 8 })
+    );
+
+    //`X` is a don't care row like `-` and not contention like in a `Signal`
+    assert_eq!(CareSignal::<u8>::from_str("X1"), CareSignal::from_str("-1"));
+    assert_eq!(format!("{:?}", CareSignal::<u8>::from_str("X1")), "[-1]");
+}
+
+#[test]
+fn test_match_mode() {
+    let exact = CareSignal::<u8>::from_str("1↑Z-");
+    let level = exact.clone().with_mode(MatchMode::Level);
+    let strong = exact.clone().with_mode(MatchMode::Strong);
+
+    let matches = |cs: &CareSignal<u8>, s| cs.matches(&Signal::from_str(s));
+    assert!(matches(&exact, "1↑Z0"));
+    assert!(!matches(&exact, "11Z0"));
+    assert!(!matches(&exact, "↑↑Z0"));
+
+    assert!(matches(&level, "1↑Z0"));
+    assert!(matches(&level, "↑1Z0"));
+    assert!(!matches(&level, "↑0Z0"));
+    assert!(!matches(&level, "↑1↑0"));
+
+    assert!(matches(&strong, "11Z0"));
+    assert!(!matches(&strong, "1↑Z0"));
+    assert!(!matches(&strong, "11↓0"));
+}
+
+#[test]
+fn test_transmission_gate() {
+    let gate = Signal::<u8>::from_str("0011");
+    assert_eq!(gate.complement(), Signal::from_str("1100"));
+    assert_eq!(Signal::transmission_gate(&gate, &Signal::from_str("01↑Z")), Some(Signal::from_str("ZZ↑Z")));
+    assert_eq!(Signal::transmission_gate(&gate, &Signal::from_str("0101")), Some(Signal::from_str("ZZ01")));
+    assert_eq!(Signal::transmission_gate(&Signal::from_str("001↑"), &gate), None);
+}
+
+#[test]
+fn test_connect_ratioed() {
+    let connect = |a, b| Signal::<u8>::connect_ratioed(&Signal::from_str(a), &Signal::from_str(b));
+
+    assert_eq!(Signal::resistor(&Signal::<u8>::from_str("01Z")), Signal::from_str("↓↑Z"));
+    assert_eq!(connect("↑↑↓Z", "0Z1↓"), Some(Signal::from_str("0↑1↓")));
+    assert_eq!(connect("1", "0"), None);
+    assert_eq!(connect("↑", "↓"), None);
+    assert_eq!(Signal::<u8>::connect(&Signal::from_str("↑"), &Signal::from_str("0")), None);
+}
+
+#[test]
+fn test_resolve() {
+    let resolve = |a, b| Signal::<u8>::resolve(&Signal::from_str(a), &Signal::from_str(b));
+
+    assert_eq!(resolve("01↑Z0", "10↓↓Z"), Signal::from_str("XXX↓0"));
+    assert_eq!(resolve("0↑X", "↑0Z"), Signal::from_str("00X"));
+    assert_eq!(resolve("X", "1"), Signal::from_str("X"));
+    assert_eq!(Signal::<u8>::from_str("0X1").contention(), 0b010);
+    assert!(!Signal::<u8>::from_str("0X1").is_strong());
+    assert_eq!(format!("{:?}", Signal::<u8>::from_str("X1")), "[X1]");
+    assert_eq!(Signal::resistor(&Signal::<u8>::from_str("X1")), Signal::from_str("X↑"));
+
+    assert_eq!(Signal::<u8>::connect_ratioed(&Signal::from_str("0↑"), &Signal::from_str("↑0")), Some(Signal::from_str("00")));
+    assert_eq!(Signal::<u8>::connect_ratioed(&Signal::from_str("01"), &Signal::from_str("↑0")), None);
+}
+
+#[test]
+fn test_weak_gate() {
+    let gate = Signal::<u8>::from_str("01↓↑ZX");
+    let drain = Signal::from_str("111111");
+
+    assert_eq!(Signal::pmos(&gate, &drain), None);
+    assert_eq!(Signal::pmos_weak_gate(&gate, &drain), Signal::from_str("1Z↑ZXX"));
+    assert_eq!(Signal::nmos_weak_gate(&gate, &Signal::from_str("000000")), Signal::from_str("Z0Z↓XX"));
+    let drain = Signal::from_str("↓↓↓↓↓Z");
+    assert_eq!(Signal::transmission_gate_weak_gate(&gate, &drain), Signal::from_str("Z↓Z↓XZ"));
+
+    //the same as the normal versions for strong gates
+    let gate = Signal::<u8>::from_str("0101");
+    let drain = Signal::from_str("↓1XZ");
+    assert_eq!(Signal::pmos(&gate, &drain), Some(Signal::pmos_weak_gate(&gate, &drain)));
+    assert_eq!(Signal::nmos(&gate, &drain), Some(Signal::nmos_weak_gate(&gate, &drain)));
+    assert_eq!(Signal::transmission_gate(&gate, &drain), Some(Signal::transmission_gate_weak_gate(&gate, &drain)));
+}
@@ -0,0 +1,34 @@
+use crate::signal::{CareSignal, Query, Signal};
+use crate::symmetry::Symmetries;
+
+#[test]
+fn test_detect_symmetries() {
+    let nand3 = Query::<u8> {
+        power: &[Signal::from_str("1111_1111"), Signal::from_str("0000_0000")],
+        inputs: &[
+            Signal::from_str("0000_1111"),
+            Signal::from_str("0011_0011"),
+            Signal::from_str("0101_0101")
+        ],
+        outputs: &[CareSignal::new(Signal::from_str("1111_1110"), 0b1111_1111)],
+    };
+    let symmetries = Symmetries::detect(&nand3);
+    assert_eq!(symmetries.len(), 6);
+
+    //all inputs are in the same class
+    let class = symmetries.class(&nand3.inputs[0]);
+    assert!(nand3.inputs.iter().all(|s| symmetries.class(s) == class));
+
+    for i in 0..symmetries.len() {
+        let inverse = symmetries.perm(symmetries.inverse(i));
+        assert_eq!(nand3.inputs[1].permute_rows(symmetries.perm(i)).permute_rows(inverse), nand3.inputs[1]);
+    }
+
+    //the enable and data input of a tristate buffer can't be swapped
+    let tristate = Query::<u8> {
+        power: &[Signal::from_str("1111"), Signal::from_str("0000")],
+        inputs: &[Signal::from_str("0011"), Signal::from_str("0101")],
+        outputs: &[CareSignal::new(Signal::from_str("0Z1Z"), 0b1111)],
+    };
+    assert_eq!(Symmetries::detect(&tristate).len(), 1);
+}
@@ -13,10 +13,7 @@ impl<T: Hash + Debug + Clone + Ord> Debug for VecSet<T> {
 
 impl<T: Ord> VecSet<T> {
     pub fn contains(&self, item: &T) -> bool {
-        match self.0.binary_search(item) {
-            Ok(_) => true,
-            Err(_) => false,
-        }
+        self.0.binary_search(item).is_ok()
     }
 
     pub fn remove(&mut self, item: &T) -> bool {